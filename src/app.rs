@@ -3,20 +3,36 @@ use egui_toast::{Toast, ToastKind, ToastOptions, Toasts};
 use egui_modal::Modal;
 use image::RgbaImage;
 use arboard::Clipboard;
-use std::sync::mpsc;
-use std::{thread, time};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
 
+mod backend_utils;
 mod crop_utils;
+mod error_utils;
 mod image_utils;
 mod painting_utils;
+mod pdf_utils;
 mod path_utils;
+mod project_utils;
+mod raster_utils;
 mod save_utils;
 mod screenshot_utils;
 mod screenshot_view;
+mod settings_utils;
+mod svg_utils;
+mod text_utils;
+mod history_utils;
 mod hotkeys_utils;
+mod window_utils;
+pub mod cli_utils;
 
+use crate::app::error_utils::YasaError;
+use crate::app::hotkeys_utils::Action;
 use crate::app::save_utils::SavePath;
 
+/// Earlier captures kept for the session PDF; older ones are dropped first, so that a long session does not fill the memory.
+const MAX_SESSION_CAPTURES: usize = 20;
+
 pub enum Views {
     Home,
     Screenshot,
@@ -25,13 +41,21 @@ pub enum Views {
 pub enum ScreenshotType {
     FullScreen,
     PartialScreen,
+    SingleScreen,
+    Window,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum ImgFormats {
     PNG,
     JPEG,
     GIF,
+    /// Editable project: the capture, its annotations and its crop.
+    YASA,
+    /// The capture embedded as PNG, with the annotations as vector shapes.
+    SVG,
+    PDF,
 }
 
 pub struct YasaApp {
@@ -40,17 +64,25 @@ pub struct YasaApp {
     screenshot_type: Option<ScreenshotType>,
     painting: Option<painting_utils::Painting>, 
     painted_screenshot: Option<egui::TextureHandle>,
+    /// Annotated images of the earlier captures of this session, PNG encoded, for the multi-page PDF.
+    session_captures: Vec<Vec<u8>>,
+    /// True while the painting is a capture not yet saved in a session PDF: opened files and pastes are not captures.
+    painting_in_session: bool,
     pub save_path: SavePath,
     screenshot_capture_view: screenshot_view::ScreenshotView,
-    update_counter: u8,     
+    scheduled_capture: Option<screenshot_utils::ScheduledCapture>,
     keyboard_shortcuts: hotkeys_utils::AllKeyboardShortcuts,
     clipboard: Option<Clipboard>,
     toasts: Toasts,
-    which_shortcut_field: String,
-    modifier: Modifiers,
-    key_var: String,
+    /// Action edited in the shortcut modal, and whether the new shortcut is added to the existing ones.
+    shortcut_action: Action,
+    shortcut_append: bool,
+    shortcut_text: String,
     ui_painting_flag: bool,
     ui_setting_flag: bool,
+    /// Folder shown by the open file panel, `None` while the panel is closed.
+    open_folder: Option<std::path::PathBuf>,
+    tool_settings: settings_utils::ToolSettings,
 }
 
 impl Default for YasaApp {
@@ -61,20 +93,24 @@ impl Default for YasaApp {
             screenshot_image_buffer: None, 
             painting: None,
             painted_screenshot: None,
+            session_captures: vec![],
+            painting_in_session: false,
             save_path: SavePath::new(
-                std::env::current_dir().unwrap().join("target"),
+                std::env::current_dir().unwrap_or_default().join("target"),
                 ImgFormats::PNG,
             ), 
             screenshot_capture_view: screenshot_view::ScreenshotView::new(),
-            update_counter: 0,
+            scheduled_capture: None,
             keyboard_shortcuts: hotkeys_utils::AllKeyboardShortcuts::default(),
             clipboard: Clipboard::new().ok(),
             toasts: Toasts::new(),
-            which_shortcut_field: "".to_string(),
-            modifier: Modifiers::CTRL,
-            key_var: "A".to_string(),
+            shortcut_action: Action::Save,
+            shortcut_append: false,
+            shortcut_text: "".to_string(),
             ui_painting_flag: false,
             ui_setting_flag: false,
+            open_folder: None,
+            tool_settings: settings_utils::ToolSettings::default(),
         }
     }
 }
@@ -84,7 +120,54 @@ impl Default for YasaApp {
 #[allow(unused_variables)]
 impl YasaApp {
     pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
-        Default::default()
+        text_utils::install_font(&cc.egui_ctx);
+        let (settings, error) = settings_utils::load();
+        let mut app = Self::from_settings(settings);
+        if let Some(e) = error {
+            app.show_error(&e);
+        }
+        app
+    }
+
+    fn from_settings(settings: settings_utils::Settings) -> Self {
+        let mut save_path = SavePath::new(settings.save_folder, settings.format);
+        save_path.name_template = settings.filename_template;
+        save_path.page_size = settings.pdf_page;
+        save_path.bake_redactions = settings.project_bake_redactions;
+        let mut screenshot_capture_view = screenshot_view::ScreenshotView::new();
+        screenshot_capture_view.timer_delay = settings.delay.clamp(0, 10);
+        Self {
+            save_path,
+            screenshot_capture_view,
+            keyboard_shortcuts: hotkeys_utils::AllKeyboardShortcuts::from_map(&settings.shortcuts),
+            tool_settings: settings.tools,
+            ..Self::default()
+        }
+    }
+
+    fn current_settings(&self) -> settings_utils::Settings {
+        let tools = match &self.painting {
+            Some(painting) => settings_utils::ToolSettings::from_tools(painting.stroke(), painting.selected_shape()),
+            None => self.tool_settings.clone(),
+        };
+        settings_utils::Settings {
+            save_folder: self.save_path.path.clone(),
+            format: self.save_path.format,
+            pdf_page: self.save_path.page_size,
+            project_bake_redactions: self.save_path.bake_redactions,
+            filename_template: self.save_path.name_template.clone(),
+            delay: self.screenshot_capture_view.get_timer_delay(),
+            shortcuts: self.keyboard_shortcuts.to_map(),
+            tools,
+            ..Default::default()
+        }
+    }
+
+    /// Writes the current settings to the config file, reporting failures as a toast.
+    pub fn store_settings(&mut self) {
+        if let Err(e) = settings_utils::store(&self.current_settings()) {
+            self.show_error(&e);
+        }
     }
 
     pub fn home_view(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
@@ -94,69 +177,58 @@ impl YasaApp {
         let dark_blue_color = egui::Color32::from_rgb(15, 22, 38);
         let dark_blue_frame = egui::Frame::default().fill(dark_blue_color).inner_margin(15.0);
 
+        // Shortcuts are disabled while a new one is being recorded.
+        let shortcuts_enabled = !Modal::new(ctx, "Assign key modal").is_open();
+
+        // Text fields paste text, not images.
+        if shortcuts_enabled && ctx.memory(|m| m.focus().is_none()) && self.keyboard_shortcuts.consume(ctx, Action::Paste) {
+            self.paste_image(ctx, _frame);
+        }
+
         egui::CentralPanel::default().frame(dark_blue_frame).show(ctx, |ui| {
-                if self.ui_painting_flag==false {
+                if !self.ui_painting_flag {
                     ui.horizontal(|ui| {
-                        if ui.button(RichText::new("📷").size(50.0)).on_hover_text("Screenshot").clicked() || ctx.input_mut(|i| i.consume_shortcut(&self.keyboard_shortcuts.take_screenshot.unwrap())){
+                        if ui.button(RichText::new("📷").size(50.0)).on_hover_text("Screenshot").clicked() || (shortcuts_enabled && self.keyboard_shortcuts.consume(ctx, Action::TakeScreenshot)) {
                             self.view = Views::Screenshot;                   
                         }
+                        if ui.button(RichText::new("📂").size(50.0)).on_hover_text("Open an image or a project").clicked() || (shortcuts_enabled && self.keyboard_shortcuts.consume(ctx, Action::OpenFile)) {
+                            self.open_folder = Some(self.save_path.path.clone());
+                        }
     
                         if self.screenshot_image_buffer.is_some() {
-                            if ui.button(RichText::new("💾").size(50.0)).on_hover_text("Save").clicked() || ctx.input_mut(|i| i.consume_shortcut(&self.keyboard_shortcuts.save.unwrap())){
-                                save_utils::save_image(
-                                    &self.save_path,
-                            self.painting.as_mut().unwrap().generate_rgba_image(),
-                                );
-                                self.toasts = Toasts::new()
-                                            .anchor(Align2::CENTER_BOTTOM, (0.0, -30.0)) 
-                                            .direction(egui::Direction::BottomUp);
-                                self.toasts.add(Toast {
-                                    text: "Image saved successfully!".into(),
-                                    kind: ToastKind::Success,
-                                    options: ToastOptions::default()
-                                    .duration_in_seconds(3.0)
-                                    .show_progress(true)
-                                });
-                                
+                            // Like paste, saving and copying leave Ctrl+S and Ctrl+C to the text field being edited.
+                            if ui.button(RichText::new("💾").size(50.0)).on_hover_text("Save").clicked() || (shortcuts_enabled && ctx.memory(|m| m.focus().is_none()) && self.keyboard_shortcuts.consume(ctx, Action::Save)) {
+                                let painting = self.painting.as_mut().unwrap();
+                                let recoverable = self.save_path.format == ImgFormats::YASA && !self.save_path.bake_redactions && painting.has_redactions();
+                                match save_utils::save(&self.save_path, painting) {
+                                    Ok(_) if recoverable => self.show_toast("Project saved, with the pixels under its redactions!", ToastKind::Warning),
+                                    Ok(_) => self.show_toast("Image saved successfully!", ToastKind::Success),
+                                    Err(e) => self.show_error(&e),
+                                }
                             }
     
                             //ui.separator();
-                            if ui.button(RichText::new("📋").size(50.0)).on_hover_text("Clipboard").clicked() || ctx.input_mut(|i| i.consume_shortcut(&self.keyboard_shortcuts.copy_to_clipboard.unwrap())){
-                                
-                                if let Some(clip) = self.clipboard.as_mut() {
-                                    let image_buffer = self.painting.as_mut().unwrap().generate_rgba_image();
-    
-                                    let arboard_image =  arboard::ImageData {
-                                        width: image_buffer.width() as usize,
-                                        height: image_buffer.height() as usize,
-                                        bytes: std::borrow::Cow::from(image_buffer.to_vec()),
-                                    };
-    
-                                    if clip.set_image(arboard_image).is_ok() {
-                                        self.toasts = Toasts::new()
-                                            .anchor(Align2::CENTER_BOTTOM, (0.0, -20.0))
-                                            .direction(egui::Direction::BottomUp);
-    
-                                        self.toasts.add(Toast {
-                                            text: "Saved to clipboard!".into(),
-                                            kind: ToastKind::Success,
-                                            options: ToastOptions::default()
-                                                .duration_in_seconds(3.0)
-                                                .show_progress(true)
-                                        });
-    
-                                    } else {
-                                        self.toasts = Toasts::new()
-                                            .anchor(Align2::CENTER_BOTTOM, (0.0, -30.0)) 
-                                            .direction(egui::Direction::BottomUp);
-    
-                                        self.toasts.add(Toast {
-                                            text: "Error :(".into(),
-                                            kind: ToastKind::Error,
-                                            options: ToastOptions::default()
-                                                .duration_in_seconds(3.0)
-                                                .show_progress(true)
-                                        });
+                            if ui.button(RichText::new("📋").size(50.0)).on_hover_text("Clipboard").clicked() || (shortcuts_enabled && ctx.memory(|m| m.focus().is_none()) && self.keyboard_shortcuts.consume(ctx, Action::CopyToClipboard)) {
+                                let image_buffer = self.painting.as_mut().unwrap().generate_rgba_image();
+                                match self.copy_to_clipboard(image_buffer) {
+                                    Ok(()) => self.show_toast("Saved to clipboard!", ToastKind::Success),
+                                    Err(e) => self.show_error(&e),
+                                }
+                            }
+                            let session_pages = self.session_captures.len() + usize::from(self.painting_in_session);
+                            if self.save_path.format == ImgFormats::PDF && session_pages > 1 {
+                                let hover = format!("Save the {} captures of this session as one PDF", session_pages);
+                                if ui.button(RichText::new("📚").size(50.0)).on_hover_text(hover).clicked() {
+                                    let earlier = self.session_captures.iter().map(|png| Ok(image_utils::decode_png(png)?));
+                                    let current = self.painting_in_session.then(|| Ok(self.painting.as_mut().unwrap().generate_rgba_image()));
+                                    match pdf_utils::save_pdf(&self.save_path, earlier.chain(current)) {
+                                        Ok(_) => {
+                                            // The next session PDF starts from the next capture.
+                                            self.session_captures.clear();
+                                            self.painting_in_session = false;
+                                            self.show_toast(format!("{} pages saved successfully!", session_pages), ToastKind::Success);
+                                        }
+                                        Err(e) => self.show_error(&e),
                                     }
                                 }
                             }
@@ -193,124 +265,107 @@ impl YasaApp {
                                     ),
                                     Default::default(),
                                 ));
-                                self.painting = Some(painting_utils::Painting::new(
+                                let mut painting = painting_utils::Painting::new(
                                     self.painted_screenshot.clone(),
                                     self.screenshot_image_buffer.clone(),
-                                ));
+                                );
+                                painting.set_tools(self.tool_settings.stroke(), self.tool_settings.shape.clone());
+                                self.painting = Some(painting);
+                                self.painting_in_session = true;
                             }
             
                             let painting = self.painting.as_mut().unwrap();
                             if self.ui_painting_flag {
+                                if shortcuts_enabled && ctx.memory(|m| m.focus().is_none()) {
+                                    painting.handle_shortcuts(ctx, &self.keyboard_shortcuts);
+                                }
                                 painting.ui_control(ui,&mut self.ui_painting_flag);
                                 
                             }
-                            painting.ui_content(ui);                       
-                            if self.screenshot_image_buffer.clone().unwrap().dimensions()
-                                != painting
-                                    .screenshot_image_buffer
-                                    .clone()
-                                    .unwrap()
-                                    .dimensions()
-                            {
-                                self.screenshot_image_buffer =
-                                   painting.screenshot_image_buffer.clone();
-                                    self.painted_screenshot = Some(ui.ctx().load_texture(
-                                        "painted_screenshot",
-                                        image_utils::load_image_from_memory(
-                                            self.screenshot_image_buffer.clone().unwrap(),
-                                        ),
-                                        Default::default(),
-                                    ));
-                                    self.painting = Some(painting_utils::Painting::new_crop(
-                                        self.painted_screenshot.clone(),
-                                        self.screenshot_image_buffer.clone(),
-                                        painting.shapes.clone()
-                                    ));
-            
-                                    _frame.set_window_size(Vec2::new((self.screenshot_image_buffer.clone().unwrap().width() as f32) / 1.5 + 50., self.screenshot_image_buffer.clone().unwrap().height() as f32 / 1.5 + 50.));
-            
-                                    ctx.request_repaint();
-                            }else{
-                                self.painting = Some(painting.clone());
+                            painting.ui_content(ui);
+                            // A crop, or undoing it, changes the size of the output: follow it.
+                            if let Some((width, height)) = painting.take_resize() {
+                                _frame.set_window_size(Vec2::new(width as f32 / 1.5 + 50., height as f32 / 1.5 + 50.));
+                                ctx.request_repaint();
                             }
-                            
                         };
                     });
                 }
             },
         );
+        if let Some(mut folder) = self.open_folder.take() {
+            let mut opened = None;
+            let mut close = false;
+            egui::SidePanel::left("open_file_panel").min_width(300.0).frame(dark_blue_frame).show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new("Open an image or a project").size(15.0));
+                    close = ui.button(RichText::new("✖").size(15.0)).on_hover_text("Close").clicked();
+                });
+                ui.separator();
+                opened = path_utils::ui_open_file(ui, &mut folder);
+            });
+            if !close {
+                self.open_folder = Some(folder);
+            }
+            if let Some(path) = opened {
+                self.open_file(ctx, _frame, &path);
+            }
+        }
+
+        // Files dropped on the window are opened in the editor.
+        let hovering_files = ctx.input(|i| !i.raw.hovered_files.is_empty());
+        if hovering_files {
+            let painter = ctx.layer_painter(LayerId::new(Order::Foreground, Id::new("file_drop")));
+            let screen = ctx.screen_rect();
+            painter.rect_filled(screen, 0.0, Color32::from_black_alpha(180));
+            painter.text(screen.center(), Align2::CENTER_CENTER, "Drop to open in YASA", FontId::proportional(24.0), Color32::WHITE);
+        }
+        if let Some(path) = ctx.input(|i| i.raw.dropped_files.iter().find_map(|file| file.path.clone())) {
+            self.open_file(ctx, _frame, &path);
+        }
+
         if self.ui_setting_flag {
             egui::SidePanel::right("my_right_setting_panel").min_width(350.0).frame(dark_blue_frame).show(ctx, |ui| {
-                if ctx.input_mut(|i| i.consume_shortcut(&self.keyboard_shortcuts.test.unwrap())) {
-                }
                 let modal = Modal::new(ctx, "Assign key modal").with_close_on_outside_click(true);
                 
                 self.toasts.show(ctx);
         
                 modal.show(|ui| {
-                    modal.title(ui, RichText::new("Write a new shortcut").strong());
+                    modal.title(ui, RichText::new(format!("Shortcut for \"{}\"", self.shortcut_action.label())).strong());
                     modal.frame(ui, |ui| {
-                        modal.body(ui, RichText::new("Insert new symbol for shortcut (A-Z. 0-9)").size(15.0));
+                        modal.body(ui, RichText::new("Press the new key combination, or type it (e.g. CTRL+SHIFT+F5)").size(15.0));
                         ui.separator();
-        
-                        ui.add(widgets::text_edit::TextEdit::singleline(&mut self.key_var).char_limit(1).hint_text("A"));
-        
-                        let tmp_modifier = if self.modifier == Modifiers::ALT {
-                            "ALT"
-                        } else if self.modifier == Modifiers::CTRL {
-                            "CTRL"
-                        } else {
-                            "SHIFT"
-                        };
-        
-                        ui.separator();
-        
-                        egui::ComboBox::from_label(RichText::new("Select a modifier").size(15.0))
-                        .selected_text(tmp_modifier)
-                        .show_ui(ui, |ui| {
-                            ui.selectable_value(&mut self.modifier, Modifiers::ALT, "ALT");
-                            ui.selectable_value(&mut self.modifier, Modifiers::CTRL, "CTRL");
-                            ui.selectable_value(&mut self.modifier, Modifiers::SHIFT, "SHIFT");
-                        });
-        
-                    modal.buttons(ui, |ui| {
-                        if modal.button(ui, RichText::new("✖").size(15.0)).clicked() {
 
-                        };
+                        if ctx.memory(|m| m.focus().is_none()) {
+                            let pressed = ctx.input(|i| i.events.iter().rev().find_map(|event| match event {
+                                Event::Key { key, pressed: true, modifiers, .. } => Some(KeyboardShortcut::new(*modifiers, *key)),
+                                _ => None,
+                            }));
+                            if let Some(shortcut) = pressed {
+                                self.shortcut_text = hotkeys_utils::format_shortcut(&shortcut);
+                            }
+                        }
+                        ui.add(widgets::text_edit::TextEdit::singleline(&mut self.shortcut_text).hint_text("CTRL+A"));
+                        ui.checkbox(&mut self.shortcut_append, "Keep the current shortcuts");
+
+                    modal.buttons(ui, |ui| {
+                        modal.button(ui, RichText::new("✖").size(15.0));
         
                         if ui.small_button(RichText::new("✔").size(15.0)).clicked() {
-        
-                            let shortcut = KeyboardShortcut::new(self.modifier, self.keyboard_shortcuts.from_name(&self.key_var));
-        
-                            if self.keyboard_shortcuts.check_if_valid(&shortcut).0 {
-                                self.keyboard_shortcuts.update_keyboard_shortcut(&self.which_shortcut_field, shortcut);
-        
-                                self.toasts = Toasts::new()
-                                                .anchor(Align2::CENTER_BOTTOM, (0.0, -20.0)) // 10 units from the bottom right corner
-                                                .direction(egui::Direction::BottomUp);
-        
-                                            self.toasts.add(Toast {
-                                                text: "Keyboard replaced succesfully!".into(),
-                                                kind: ToastKind::Success,
-                                                options: ToastOptions::default()
-                                                    .duration_in_seconds(3.0)
-                                                    .show_progress(true)
-                                            });
-                                
-                                modal.close();
-                                
-                            } else {
-                                self.toasts = Toasts::new()
-                                                .anchor(Align2::CENTER_BOTTOM, (0.0, -20.0)) // 10 units from the bottom right corner
-                                                .direction(egui::Direction::BottomUp);
-        
-                                            self.toasts.add(Toast {
-                                                text: format!("Keyboard shortcut already in use by action {:?}!", self.keyboard_shortcuts.check_if_valid(&shortcut).1).into(),
-                                                kind: ToastKind::Error,
-                                                options: ToastOptions::default()
-                                                    .duration_in_seconds(3.0)
-                                                    .show_progress(true)
-                                            });
+                            let result = hotkeys_utils::parse_shortcut(&self.shortcut_text).and_then(|shortcut| {
+                                if self.shortcut_append {
+                                    self.keyboard_shortcuts.add(self.shortcut_action, shortcut)
+                                } else {
+                                    self.keyboard_shortcuts.replace(self.shortcut_action, shortcut)
+                                }
+                            });
+                            match result {
+                                Ok(()) => {
+                                    self.store_settings();
+                                    self.show_toast("Keyboard shortcut updated succesfully!", ToastKind::Success);
+                                    modal.close();
+                                }
+                                Err(e) => self.show_error(&e),
                             }
                         }
                     });
@@ -324,6 +379,7 @@ impl YasaApp {
                     ui.allocate_space(Vec2::new(0.0, 15.0));
                     if ui.button(RichText::new("🏠").size(30.0)).on_hover_text("Go back Home").clicked() {
                         self.ui_setting_flag = false;
+                        self.store_settings();
                     };
                     ui.allocate_space(Vec2::new(0.0, 15.0));
                     ui.separator();
@@ -352,52 +408,38 @@ impl YasaApp {
                             ui.strong(RichText::new("New Shortcut").size(13.0));
                         });
                     }).body(|mut body| {
-                        
-                        body.row(30.0, |mut row| {
-                            row.col(|ui|{
-                                ui.label(RichText::new("Show save view").size(13.0));
-                            });
-                            row.col(|ui|{
-                                ui.label(self.keyboard_shortcuts.human_readable_shorcut("save"));
-                            });
-                            row.col(|ui|{
-                                if ui.button(RichText::new("✏").size(20.0)).on_hover_text("Edit").clicked() {
-                                    self.which_shortcut_field = "save".to_string();
-                                    modal.open();
-                                }
-                            });
-                        });
-
-                        body.row(30.0, |mut row| {
-                            row.col(|ui|{
-                                ui.label(RichText::new("Copy image to clipboard").size(13.0));
-                            });
-                            row.col(|ui|{
-                                ui.label(self.keyboard_shortcuts.human_readable_shorcut("copy_to_clipboard"));
-                            });
-                            row.col(|ui|{
-                                if ui.button(RichText::new("✏").size(20.0)).on_hover_text("Edit").clicked() {
-                                    self.which_shortcut_field = "copy_to_clipboard".to_string();
-                                    modal.open();
-                                }
-                            });
-                        });
-
-                        body.row(30.0, |mut row| {
-                            row.col(|ui|{
-                                ui.label(RichText::new("Take a screenshot").size(13.0));
-                            });
-                            row.col(|ui|{
-                                ui.label(self.keyboard_shortcuts.human_readable_shorcut("take_screenshot"));
-                            });
-                            row.col(|ui|{
-                                if ui.button(RichText::new("✏").size(20.0)).on_hover_text("Edit").clicked() {
-                                    self.which_shortcut_field = "take_screenshot".to_string();
-                                    modal.open();
-                                }
+                        for action in Action::ALL {
+                            body.row(30.0, |mut row| {
+                                row.col(|ui|{
+                                    ui.label(RichText::new(action.label()).size(13.0));
+                                });
+                                row.col(|ui|{
+                                    ui.label(self.keyboard_shortcuts.human_readable_shorcut(action));
+                                });
+                                row.col(|ui|{
+                                    ui.horizontal(|ui| {
+                                        if ui.button(RichText::new("✏").size(20.0)).on_hover_text("Edit").clicked() {
+                                            self.shortcut_action = action;
+                                            self.shortcut_append = false;
+                                            self.shortcut_text.clear();
+                                            modal.open();
+                                        }
+                                        if ui.button(RichText::new("➕").size(20.0)).on_hover_text("Add another shortcut").clicked() {
+                                            self.shortcut_action = action;
+                                            self.shortcut_append = true;
+                                            self.shortcut_text.clear();
+                                            modal.open();
+                                        }
+                                        if ui.button(RichText::new("↺").size(20.0)).on_hover_text("Restore default").clicked() {
+                                            match self.keyboard_shortcuts.reset(action) {
+                                                Ok(()) => self.store_settings(),
+                                                Err(e) => self.show_error(&e),
+                                            }
+                                        }
+                                    });
+                                });
                             });
-                        });
-                        
+                        }
                     });
         
                     ui.separator();
@@ -408,55 +450,203 @@ impl YasaApp {
         }
     
     pub fn screenshot_view(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        if let Some(scheduled_capture) = &self.scheduled_capture {
+            let remaining = scheduled_capture.remaining();
+            if remaining.is_zero() {
+                _frame.set_visible(false);
+            } else if self.screenshot_capture_view.countdown_ui(ctx, _frame, remaining)
+                || ctx.input(|i| i.key_pressed(Key::Escape))
+            {
+                scheduled_capture.cancel();
+                _frame.set_always_on_top(false);
+                self.scheduled_capture = None;
+                self.screenshot_type = None;
+            } else {
+                ctx.request_repaint_after(remaining.min(Duration::from_millis(100)));
+            }
+            return;
+        }
+
+        self.screenshot_capture_view.ui(ctx,_frame, &mut self.view, &mut self.screenshot_type);
+        if let Some(screenshot_type) = &self.screenshot_type {
+            let capture_area = self
+                .screenshot_capture_view
+                .capture_area(screenshot_type, ctx.pixels_per_point());
+            let delay = Duration::from_secs(self.screenshot_capture_view.get_timer_delay().max(0) as u64);
+            self.scheduled_capture = Some(screenshot_utils::ScheduledCapture::start(capture_area, delay, ctx.clone()));
+            if delay.is_zero() {
+                _frame.set_visible(false);
+            }
+        }
+
         if self.screenshot_type.is_none() {
-            self.update_counter = 0;
+            _frame.set_visible(true);
+        }
+    }
+
+    fn copy_to_clipboard(&mut self, image_buffer: RgbaImage) -> Result<(), YasaError> {
+        let clip = self
+            .clipboard
+            .as_mut()
+            .ok_or_else(|| YasaError::Clipboard("no clipboard available".to_string()))?;
+        let arboard_image = arboard::ImageData {
+            width: image_buffer.width() as usize,
+            height: image_buffer.height() as usize,
+            bytes: std::borrow::Cow::from(image_buffer.into_raw()),
+        };
+        clip.set_image(arboard_image)?;
+        Ok(())
+    }
+
+    fn image_from_clipboard(&mut self) -> Result<RgbaImage, YasaError> {
+        let clip = self
+            .clipboard
+            .as_mut()
+            .ok_or_else(|| YasaError::Clipboard("no clipboard available".to_string()))?;
+        let image = clip.get_image()?;
+        if image.width == 0 || image.height == 0 {
+            return Err(YasaError::Clipboard("the image in the clipboard is empty".to_string()));
+        }
+        RgbaImage::from_raw(image.width as u32, image.height as u32, image.bytes.into_owned())
+            .ok_or_else(|| YasaError::Clipboard("the image in the clipboard is malformed".to_string()))
+    }
+
+    /// Pastes the image in the clipboard over the screenshot being edited, or opens it in the editor.
+    fn paste_image(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        let image = match self.image_from_clipboard() {
+            Ok(image) => image,
+            Err(e) => return self.show_error(&e),
+        };
+        match self.painting.as_mut() {
+            Some(painting) if self.ui_painting_flag => painting.paste_image(image),
+            _ => {
+                let (width, height) = image.dimensions();
+                self.load_project(ctx, project_utils::Project { image, shapes: vec![], crop: None });
+                self.save_path.name = save_utils::generate_filename(&self.save_path.name_template);
+                _frame.set_window_size(egui::Vec2::new(width as f32, height as f32));
+            }
+        }
+    }
+
+    fn show_toast(&mut self, text: impl Into<WidgetText>, kind: ToastKind) {
+        self.toasts = Toasts::new()
+            .anchor(Align2::CENTER_BOTTOM, (0.0, -30.0))
+            .direction(egui::Direction::BottomUp);
+        self.toasts.add(Toast {
+            text: text.into(),
+            kind,
+            options: ToastOptions::default()
+                .duration_in_seconds(3.0)
+                .show_progress(true)
+        });
+    }
+
+    fn show_error(&mut self, error: &YasaError) {
+        let mut text = error.to_string();
+        if let Some(first) = text.get_mut(0..1) {
+            first.make_ascii_uppercase();
+        }
+        self.show_toast(text, ToastKind::Error);
+    }
+
+    /// Opens an image, or a `.yasa` project with every annotation still editable, in the editor.
+    /// Returns false, after showing the error, if the file cannot be read.
+    pub fn open_path(&mut self, ctx: &egui::Context, path: &std::path::Path) -> bool {
+        if !path_utils::can_open(path) {
+            self.show_error(&YasaError::CannotOpen(path.to_path_buf()));
+            return false;
+        }
+        let project = if path.extension().map_or(false, |extension| extension.eq_ignore_ascii_case(project_utils::PROJECT_EXTENSION)) {
+            project_utils::open_project(path)
         } else {
-            self.update_counter += 1;
+            image::open(path)
+                .map(|image| project_utils::Project { image: image.to_rgba8(), shapes: vec![], crop: None })
+                .map_err(YasaError::from)
+        };
+        match project {
+            Ok(project) => self.load_project(ctx, project),
+            Err(e) => {
+                self.show_error(&e);
+                return false;
+            }
         }
-        self.screenshot_capture_view.ui(ctx,_frame, &mut self.view, &mut self.screenshot_type);
-        if self.screenshot_type.is_some() {
+        if let Some(name) = path.file_stem() {
+            self.save_path.name = name.to_string_lossy().to_string();
+        }
+        true
+    }
 
-            if self.update_counter == 2 {
-                
-                thread::sleep(time::Duration::from_millis(150 + (self.screenshot_capture_view.get_timer_delay()*1000) as u64));
-
-                let (tx_screenshot_buffer, rx_screenshot_buffer) = mpsc::channel();
-                let tmp_screenshot_type = self.screenshot_type.clone();
-                let ctx1 = ctx.clone();
-                if self.screenshot_type.clone().unwrap() == ScreenshotType::FullScreen {
-                    thread::spawn(move || {
-                        let screenshot_image_buffer =
-                            screenshot_utils::take_screenshot(tmp_screenshot_type, None, &ctx1);
-                        tx_screenshot_buffer.send(screenshot_image_buffer).unwrap();
-                    });
-                } else if self.screenshot_type.clone().unwrap() == ScreenshotType::PartialScreen {
-                    let grab = self.screenshot_capture_view.clone();
-                    thread::spawn(move || {
-                        let screenshot_image_buffer =
-                            screenshot_utils::take_screenshot(tmp_screenshot_type, Some(grab), &ctx1);
-                        tx_screenshot_buffer.send(screenshot_image_buffer).unwrap();
-                    });
-                }
-    
-                self.screenshot_image_buffer = rx_screenshot_buffer.recv().unwrap();
-    
-                if self.screenshot_image_buffer.is_some() {
-                    self.save_path.name = save_utils::generate_filename();
+    /// Shows `project` in the editor, keeping the tools chosen for the previous screenshot.
+    fn load_project(&mut self, ctx: &egui::Context, project: project_utils::Project) {
+        self.close_painting();
+        let texture = ctx.load_texture(
+            "painted_screenshot",
+            image_utils::load_image_from_memory(project.image.clone()),
+            Default::default(),
+        );
+        self.screenshot_image_buffer = Some(project.image.clone());
+        self.painted_screenshot = Some(texture.clone());
+        let mut painting = painting_utils::Painting::from_project(texture, project);
+        painting.set_tools(self.tool_settings.stroke(), self.tool_settings.shape.clone());
+        self.painting = Some(painting);
+        self.painting_in_session = false;
+        self.view = Views::Home;
+        self.ui_painting_flag = true;
+    }
+
+    /// Leaves the current screenshot for a new one: its tools are kept for the next and, if it is a capture, its image for the session PDF.
+    fn close_painting(&mut self) {
+        if let Some(mut painting) = self.painting.take() {
+            self.tool_settings = settings_utils::ToolSettings::from_tools(painting.stroke(), painting.selected_shape());
+            // Captures are only kept while PDF is the chosen format, compressed so that a long session stays small.
+            if std::mem::take(&mut self.painting_in_session) && self.save_path.format == ImgFormats::PDF {
+                match image_utils::encode_png(&painting.generate_rgba_image()) {
+                    Ok(png) => {
+                        if self.session_captures.len() == MAX_SESSION_CAPTURES {
+                            self.session_captures.remove(0);
+                        }
+                        self.session_captures.push(png);
+                    }
+                    Err(e) => self.show_error(&e.into()),
                 }
-                self.view = Views::Home;
-                self.screenshot_type = None;
-                self.painting = None;
-                _frame.set_window_size(egui::Vec2::new(self.screenshot_image_buffer.as_mut().unwrap().width() as f32, self.screenshot_image_buffer.as_mut().unwrap().height() as f32));
-                _frame.set_centered();
-                _frame.set_visible(true);
-                _frame.set_decorations(true);
             }
+        }
+    }
 
+    /// Opens `path` from the window, which is resized to the image like after a capture.
+    fn open_file(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame, path: &std::path::Path) {
+        if self.open_path(ctx, path) {
+            self.open_folder = None;
+            let (width, height) = self.screenshot_image_buffer.as_ref().unwrap().dimensions();
+            _frame.set_window_size(egui::Vec2::new(width as f32, height as f32));
         }
+    }
 
-        if self.screenshot_type.is_none() {
-            _frame.set_visible(true);
+    /// Picks up the result of a scheduled capture, if it has arrived, and goes back to the editor.
+    pub fn poll_scheduled_capture(&mut self, _frame: &mut eframe::Frame) {
+        let Some(result) = self.scheduled_capture.as_ref().and_then(|s| s.try_result()) else {
+            return;
+        };
+        self.scheduled_capture = None;
+        self.screenshot_type = None;
+        self.view = Views::Home;
+
+        match result {
+            Ok(screenshot_image_buffer) => {
+                self.save_path.name = save_utils::generate_filename(&self.save_path.name_template);
+                _frame.set_window_size(egui::Vec2::new(screenshot_image_buffer.width() as f32, screenshot_image_buffer.height() as f32));
+                self.screenshot_image_buffer = Some(screenshot_image_buffer);
+                self.close_painting();
+            }
+            Err(e) => {
+                _frame.set_window_size(egui::Vec2::new(640.0, 400.0));
+                self.show_error(&e);
+            }
         }
+        _frame.set_always_on_top(false);
+        _frame.set_centered();
+        _frame.set_visible(true);
+        _frame.set_decorations(true);
     }
 
 
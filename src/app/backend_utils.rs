@@ -0,0 +1,126 @@
+use super::error_utils::YasaError;
+use image::RgbaImage;
+#[cfg(test)]
+use image::Rgba;
+use screenshots::Screen;
+
+/// Position and size of a display, as reported by the platform.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DisplayGeometry {
+    pub id: u32,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub scale_factor: f32,
+    pub is_primary: bool,
+}
+
+impl DisplayGeometry {
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && y >= self.y && x < self.x + self.width as i32 && y < self.y + self.height as i32
+    }
+}
+
+/// Source of display captures, so that the capture path does not depend on a real screen.
+pub trait CaptureBackend: Send {
+    fn displays(&self) -> Result<Vec<DisplayGeometry>, YasaError>;
+    fn capture(&self, display: &DisplayGeometry) -> Result<RgbaImage, YasaError>;
+}
+
+/// Captures the real displays through the `screenshots` crate.
+pub struct ScreenshotsBackend;
+
+impl CaptureBackend for ScreenshotsBackend {
+    fn displays(&self) -> Result<Vec<DisplayGeometry>, YasaError> {
+        let screens = Screen::all().map_err(|e| YasaError::Capture(e.to_string()))?;
+        Ok(
+            screens
+                .iter()
+                .map(|screen| {
+                    let info = screen.display_info;
+                    DisplayGeometry {
+                        id: info.id,
+                        x: info.x,
+                        y: info.y,
+                        width: info.width,
+                        height: info.height,
+                        scale_factor: info.scale_factor,
+                        is_primary: info.is_primary,
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    fn capture(&self, display: &DisplayGeometry) -> Result<RgbaImage, YasaError> {
+        let screen = Screen::all()
+            .map_err(|e| YasaError::Capture(e.to_string()))?
+            .into_iter()
+            .find(|screen| screen.display_info.id == display.id)
+            .ok_or(YasaError::DisplayNotFound(display.id))?;
+        let image = screen.capture().map_err(|e| YasaError::Capture(e.to_string()))?;
+        RgbaImage::from_raw(image.width(), image.height(), image.rgba().clone())
+            .ok_or_else(|| YasaError::Capture("unexpected image buffer size".to_string()))
+    }
+}
+
+/// In-memory displays with fixed contents, for testing the capture path without a screen.
+#[cfg(test)]
+pub struct FakeBackend {
+    screens: Vec<(DisplayGeometry, RgbaImage)>,
+}
+
+#[cfg(test)]
+impl FakeBackend {
+    pub fn new(screens: Vec<(DisplayGeometry, RgbaImage)>) -> Self {
+        Self { screens }
+    }
+
+    /// Two side by side displays filled with distinct gradients.
+    pub fn sample() -> Self {
+        let left = DisplayGeometry {
+            id: 1,
+            x: 0,
+            y: 0,
+            width: 640,
+            height: 480,
+            scale_factor: 1.0,
+            is_primary: true,
+        };
+        let right = DisplayGeometry {
+            id: 2,
+            x: 640,
+            y: 0,
+            width: 320,
+            height: 240,
+            scale_factor: 1.0,
+            is_primary: false,
+        };
+        let gradient = |display: &DisplayGeometry, blue: u8| {
+            RgbaImage::from_fn(display.width, display.height, |x, y| {
+                Rgba([(x * 255 / display.width) as u8, (y * 255 / display.height) as u8, blue, 255])
+            })
+        };
+        Self::new(vec![(left, gradient(&left, 0)), (right, gradient(&right, 255))])
+    }
+}
+
+#[cfg(test)]
+impl CaptureBackend for FakeBackend {
+    fn displays(&self) -> Result<Vec<DisplayGeometry>, YasaError> {
+        Ok(self.screens.iter().map(|(display, _)| *display).collect())
+    }
+
+    fn capture(&self, display: &DisplayGeometry) -> Result<RgbaImage, YasaError> {
+        self.screens
+            .iter()
+            .find(|(d, _)| d.id == display.id)
+            .map(|(_, image)| image.clone())
+            .ok_or(YasaError::DisplayNotFound(display.id))
+    }
+}
+
+pub fn default_backend() -> Box<dyn CaptureBackend> {
+    Box::new(ScreenshotsBackend)
+}
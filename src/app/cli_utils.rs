@@ -0,0 +1,334 @@
+use crate::app::backend_utils;
+use crate::app::save_utils::{self, SavePath};
+use crate::app::screenshot_utils::{self, CaptureArea};
+use crate::app::ImgFormats;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Usage of `yasa` itself, which opens the editor.
+const EDITOR_USAGE: &str = "Usage: yasa [FILE]
+       yasa capture [OPTIONS]
+
+  FILE    image or .yasa project to open in the editor
+  capture take a screenshot without opening any window, see `yasa capture --help`";
+
+const USAGE: &str = "Usage: yasa capture (--full | --monitor N | --region X,Y,W,H) [--format png|jpeg|gif] [--out PATH] [--delay SECONDS] [--json]
+
+  --full              capture every display stitched together (default)
+  --monitor N         capture only the N-th display (0 is the first one)
+  --region X,Y,W,H    capture a region, in physical pixels from the top left corner of the first display
+  --format FORMAT     output format, defaults to the extension of --out or png
+  --out PATH          output file, written as given; defaults to a timestamped name in the current directory
+  --delay SECONDS     wait before capturing
+  --json              print the result as JSON instead of the bare path";
+
+#[derive(Debug, PartialEq)]
+enum CaptureTarget {
+    Full,
+    Monitor(usize),
+    Region(i32, i32, u32, u32),
+}
+
+#[derive(Debug, PartialEq)]
+struct CaptureArgs {
+    target: CaptureTarget,
+    /// Given with `--format`, or else taken from the extension of `out`.
+    format: ImgFormats,
+    out: Option<PathBuf>,
+    delay: Duration,
+    json: bool,
+}
+
+/// File to open in the editor, given as the only argument of `yasa`.
+/// Options are not files: help is printed, anything else is an error, and the process exit code returned instead.
+pub fn editor_file(args: &[String]) -> Result<Option<PathBuf>, i32> {
+    match args {
+        [] => Ok(None),
+        [arg] if arg == "-h" || arg == "--help" => {
+            println!("{}", EDITOR_USAGE);
+            Err(0)
+        }
+        [arg] if arg.starts_with('-') => {
+            eprintln!("yasa: unknown option {}\n\n{}", arg, EDITOR_USAGE);
+            Err(2)
+        }
+        [file] => Ok(Some(PathBuf::from(file))),
+        [_, extra, ..] => {
+            eprintln!("yasa: unexpected argument {}\n\n{}", extra, EDITOR_USAGE);
+            Err(2)
+        }
+    }
+}
+
+/// Runs `yasa capture ...` without opening any window, returning the process exit code.
+pub fn run_capture(args: &[String]) -> i32 {
+    #[cfg(windows)]
+    attach_parent_console();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return 0;
+    }
+    let args = match parse_capture_args(args) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("yasa: {}\n\n{}", e, USAGE);
+            return 2;
+        }
+    };
+
+    match capture(&args) {
+        Ok((path, width, height)) => {
+            if args.json {
+                println!(
+                    "{}",
+                    serde_json::json!({ "path": path.to_string_lossy(), "width": width, "height": height })
+                );
+            } else {
+                println!("{}", path.display());
+            }
+            0
+        }
+        Err(e) => {
+            if args.json {
+                println!("{}", serde_json::json!({ "error": e }));
+            }
+            eprintln!("yasa: {}", e);
+            1
+        }
+    }
+}
+
+/// Windows release builds are GUI programs without a console of their own: print to the one of the shell
+/// that started `yasa capture`. Without one (e.g. started from Explorer) the output is lost, only the exit code remains.
+#[cfg(windows)]
+fn attach_parent_console() {
+    use windows_sys::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+    // SAFETY: no pointer is involved, a failure only leaves the process without a console.
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+fn capture(args: &CaptureArgs) -> Result<(PathBuf, u32, u32), String> {
+    std::thread::sleep(args.delay);
+    let backend = backend_utils::default_backend();
+    let area = match args.target {
+        CaptureTarget::Full => CaptureArea::AllScreens,
+        CaptureTarget::Monitor(index) => {
+            let ids = screenshot_utils::display_ids(backend.as_ref()).map_err(|e| e.to_string())?;
+            let id = ids
+                .get(index)
+                .ok_or_else(|| format!("monitor {} not found ({} available)", index, ids.len()))?;
+            CaptureArea::Screen(*id)
+        }
+        CaptureTarget::Region(x, y, width, height) => CaptureArea::Region { x, y, width, height },
+    };
+    let image = screenshot_utils::take_screenshot(backend.as_ref(), area).map_err(|e| e.to_string())?;
+    let (width, height) = image.dimensions();
+
+    let path = match &args.out {
+        Some(out) => {
+            save_utils::write_image(out, args.format, image).map_err(|e| e.to_string())?;
+            out.clone()
+        }
+        None => {
+            let mut save_path = SavePath::new(std::env::current_dir().map_err(|e| e.to_string())?, args.format);
+            save_path.name = save_utils::generate_filename(save_utils::DEFAULT_NAME_TEMPLATE);
+            save_utils::save_image(&save_path, image).map_err(|e| e.to_string())?
+        }
+    };
+    Ok((path, width, height))
+}
+
+fn parse_capture_args(args: &[String]) -> Result<CaptureArgs, String> {
+    let mut target = None;
+    let mut format = None;
+    let mut out = None;
+    let mut delay = Duration::ZERO;
+    let mut json = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("missing value for {}", arg));
+        let new_target = match arg.as_str() {
+            "--full" => Some(CaptureTarget::Full),
+            "--monitor" => {
+                let value = value()?;
+                let index = value.parse().map_err(|_| format!("invalid monitor number '{}'", value))?;
+                Some(CaptureTarget::Monitor(index))
+            }
+            "--region" => Some(parse_region(value()?)?),
+            "--format" => {
+                let value = value()?;
+                format = Some(parse_format(value).ok_or_else(|| format!("unsupported format '{}'", value))?);
+                None
+            }
+            "--out" => {
+                out = Some(PathBuf::from(value()?));
+                None
+            }
+            "--delay" => {
+                let value = value()?;
+                let seconds = value.parse().map_err(|_| format!("invalid delay '{}'", value))?;
+                delay = Duration::from_secs(seconds);
+                None
+            }
+            "--json" => {
+                json = true;
+                None
+            }
+            other => return Err(format!("unknown argument '{}'", other)),
+        };
+        if let Some(new_target) = new_target {
+            if target.is_some() {
+                return Err("only one of --full, --monitor and --region can be given".to_string());
+            }
+            target = Some(new_target);
+        }
+    }
+
+    let format = match (format, out.as_deref().and_then(|out| out.extension())) {
+        (Some(format), Some(extension)) => match parse_format(&extension.to_string_lossy()) {
+            Some(implied) if implied != format => {
+                return Err(format!("--format does not match the extension '{}' of --out", extension.to_string_lossy()))
+            }
+            _ => format,
+        },
+        (Some(format), None) => format,
+        (None, None) => ImgFormats::PNG,
+        (None, Some(extension)) => parse_format(&extension.to_string_lossy())
+            .ok_or_else(|| format!("unsupported extension '{}', give the format with --format", extension.to_string_lossy()))?,
+    };
+    Ok(CaptureArgs {
+        target: target.unwrap_or(CaptureTarget::Full),
+        format,
+        out,
+        delay,
+        json,
+    })
+}
+
+fn parse_region(value: &str) -> Result<CaptureTarget, String> {
+    let invalid = || format!("invalid region '{}', expected X,Y,W,H", value);
+    let parts: Vec<&str> = value.split(',').map(str::trim).collect();
+    if parts.len() != 4 {
+        return Err(invalid());
+    }
+    let x = parts[0].parse().map_err(|_| invalid())?;
+    let y = parts[1].parse().map_err(|_| invalid())?;
+    let width: u32 = parts[2].parse().map_err(|_| invalid())?;
+    let height: u32 = parts[3].parse().map_err(|_| invalid())?;
+    if width == 0 || height == 0 {
+        return Err(invalid());
+    }
+    Ok(CaptureTarget::Region(x, y, width, height))
+}
+
+fn parse_format(value: &str) -> Option<ImgFormats> {
+    match value.to_ascii_lowercase().as_str() {
+        "png" => Some(ImgFormats::PNG),
+        "jpeg" | "jpg" => Some(ImgFormats::JPEG),
+        "gif" => Some(ImgFormats::GIF),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn parse(args: &[&str]) -> Result<CaptureArgs, String> {
+        parse_capture_args(&strings(args))
+    }
+
+    #[test]
+    fn editor_opens_the_file_given() {
+        assert_eq!(editor_file(&[]), Ok(None));
+        assert_eq!(editor_file(&strings(&["notes.yasa"])), Ok(Some(PathBuf::from("notes.yasa"))));
+        assert_eq!(editor_file(&strings(&["./-dash.png"])), Ok(Some(PathBuf::from("./-dash.png"))));
+    }
+
+    #[test]
+    fn editor_rejects_options() {
+        assert_eq!(editor_file(&strings(&["--help"])), Err(0));
+        assert_eq!(editor_file(&strings(&["-h"])), Err(0));
+        assert_eq!(editor_file(&strings(&["--full"])), Err(2));
+        assert_eq!(editor_file(&strings(&["-"])), Err(2));
+        assert_eq!(editor_file(&strings(&["a.png", "b.png"])), Err(2));
+    }
+
+    #[test]
+    fn defaults_to_a_full_png_capture() {
+        let args = parse(&[]).unwrap();
+        assert_eq!(
+            args,
+            CaptureArgs { target: CaptureTarget::Full, format: ImgFormats::PNG, out: None, delay: Duration::ZERO, json: false }
+        );
+    }
+
+    #[test]
+    fn parses_targets() {
+        assert_eq!(parse(&["--monitor", "1"]).unwrap().target, CaptureTarget::Monitor(1));
+        assert_eq!(parse(&["--region", "-10, 20,300,200"]).unwrap().target, CaptureTarget::Region(-10, 20, 300, 200));
+    }
+
+    #[test]
+    fn format_option_wins_over_the_extension() {
+        let args = parse(&["--out", "shots/shot.tmp", "--format", "png"]).unwrap();
+        assert_eq!(args.format, ImgFormats::PNG);
+        assert_eq!(args.out, Some(PathBuf::from("shots/shot.tmp")));
+        assert_eq!(parse(&["--out", "shot.JPG", "--format", "jpeg"]).unwrap().format, ImgFormats::JPEG);
+        assert_eq!(parse(&["--format", "JPG"]).unwrap().format, ImgFormats::JPEG);
+        assert_eq!(parse(&["--format", "gif"]).unwrap().format, ImgFormats::GIF);
+    }
+
+    #[test]
+    fn format_comes_from_the_out_extension() {
+        assert_eq!(parse(&["--out", "shot.jpg"]).unwrap().format, ImgFormats::JPEG);
+        assert_eq!(parse(&["--out", "shot.JPEG"]).unwrap().format, ImgFormats::JPEG);
+        assert_eq!(parse(&["--out", "shot.gif"]).unwrap().format, ImgFormats::GIF);
+        assert_eq!(parse(&["--out", "shot"]).unwrap().format, ImgFormats::PNG);
+        assert!(parse(&["--out", "shot.bmp"]).is_err());
+    }
+
+    #[test]
+    fn rejects_a_format_other_than_the_extension() {
+        assert!(parse(&["--format", "png", "--out", "shot.jpg"]).is_err());
+        assert!(parse(&["--out", "shot.gif", "--format", "jpeg"]).is_err());
+    }
+
+    #[test]
+    fn parses_delay_and_json() {
+        let args = parse(&["--delay", "3", "--json"]).unwrap();
+        assert_eq!(args.delay, Duration::from_secs(3));
+        assert!(args.json);
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        assert!(parse(&["--full", "--monitor", "0"]).is_err());
+        assert!(parse(&["--monitor"]).is_err());
+        assert!(parse(&["--monitor", "first"]).is_err());
+        assert!(parse(&["--region", "1,2,3"]).is_err());
+        assert!(parse(&["--region", "0,0,0,10"]).is_err());
+        assert!(parse(&["--format", "bmp"]).is_err());
+        assert!(parse(&["--delay", "-1"]).is_err());
+        assert!(parse(&["--delay", "soon"]).is_err());
+        assert!(parse(&["--wat"]).is_err());
+    }
+
+    #[test]
+    fn writes_exactly_the_out_path() {
+        let folder = std::env::temp_dir().join(format!("yasa-cli-{}", std::process::id()));
+        std::fs::create_dir_all(&folder).unwrap();
+        let out = folder.join("shot.jpg");
+        save_utils::write_image(&out, ImgFormats::JPEG, image::RgbaImage::new(3, 2)).unwrap();
+        assert_eq!(image::open(&out).unwrap().width(), 3);
+        assert!(!folder.join("shot.jpeg").exists());
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+}
@@ -1,244 +1,388 @@
 use egui::*;
-use egui::epaint::RectShape;
+use egui::emath::RectTransform;
+
+/// Smallest side of the crop, in pixels of the screenshot.
+const MIN_SIZE: f32 = 8.0;
+/// Distance in screen points from a side or a corner that still grabs it.
+const GRIP_DISTANCE: f32 = 10.0;
+
+/// Shape the crop is locked to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AspectRatio {
+    Free,
+    Square,
+    FourThree,
+    SixteenNine,
+    /// Width and height of the ratio typed by the user.
+    Custom(u32, u32),
+}
 
+impl AspectRatio {
+    /// Width divided by height, `None` when the crop is free.
+    fn value(&self) -> Option<f32> {
+        match self {
+            AspectRatio::Free => None,
+            AspectRatio::Square => Some(1.0),
+            AspectRatio::FourThree => Some(4.0 / 3.0),
+            AspectRatio::SixteenNine => Some(16.0 / 9.0),
+            AspectRatio::Custom(width, height) => Some(*width.max(&1) as f32 / *height.max(&1) as f32),
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            AspectRatio::Free => "Free",
+            AspectRatio::Square => "1:1",
+            AspectRatio::FourThree => "4:3",
+            AspectRatio::SixteenNine => "16:9",
+            AspectRatio::Custom(..) => "Custom",
+        }
+    }
+}
+
+/// Side of the crop along one axis: left or top, right or bottom.
+#[derive(Clone, Copy, PartialEq)]
+enum Edge {
+    Start,
+    End,
+}
+
+/// What the mouse grabbed: the whole crop, or the sides it is resizing (two of them for a corner).
+#[derive(Clone, Copy)]
+enum Grip {
+    Move,
+    Resize { x: Option<Edge>, y: Option<Edge> },
+}
+
+/// Drag in progress: the grip, and the crop and the pointer (in pixels) when it started.
 #[derive(Clone)]
-enum Side {
-    None,
-    Left,
-    Right,
-    Up,
-    Down,
-    Center
+struct Drag {
+    grip: Grip,
+    start_rect: Rect,
+    start_pointer: Pos2,
 }
 
 #[derive(Clone)]
 pub struct Crop {
-    cut_rect: Rect,
-    scaled_rect: Rect,
-    pub offset_x_right: f32,
-    pub offset_x_left: f32,
-    pub offset_y_up: f32,
-    pub offset_y_down: f32,
-    last_click: Pos2,
-    limit_reached: bool,
-    side: Side,
+    /// The kept area, in pixels of the screenshot.
+    rect: Rect,
+    /// The whole screenshot, in pixels.
+    bounds: Rect,
+    aspect_ratio: AspectRatio,
+    drag: Option<Drag>,
 }
 
-impl Default for Crop {
-    fn default() -> Self {
+impl Crop {
+    /// Starts from the whole image.
+    pub fn new(image_size: Vec2) -> Self {
+        Self::from_area(Rect::from_min_size(Pos2::ZERO, image_size), image_size)
+    }
+
+    /// Starts from `area`, a rectangle in pixels of an image of `image_size`.
+    pub fn from_area(area: Rect, image_size: Vec2) -> Self {
         Self {
-            cut_rect: Rect::NOTHING,
-            scaled_rect: Rect::NOTHING,
-            offset_x_right: 0.0,
-            offset_x_left: 0.0,
-            offset_y_up: 0.0,
-            offset_y_down: 0.0,
-            side: Side::None,
-            limit_reached: false,
-            last_click: Pos2::default(),
+            rect: area,
+            bounds: Rect::from_min_size(Pos2::ZERO, image_size),
+            aspect_ratio: AspectRatio::Free,
+            drag: None,
         }
     }
-}
 
-impl Crop {
-    pub fn new() -> Self {
-        Self::default()
+    /// The kept area, in whole pixels of the screenshot.
+    pub fn area(&self) -> Rect {
+        Rect::from_min_max(self.rect.min.round(), self.rect.max.round())
     }
 
-    pub fn crop_img(&mut self, ui: &mut Ui, response: Response, dim: Vec2){
+    /// Draws the crop over the canvas and, if `interactive`, lets the mouse move it or resize it from its sides and corners.
+    /// `to_screen` maps pixels of the screenshot to the canvas.
+    pub fn crop_img(&mut self, ui: &mut Ui, response: &Response, to_screen: RectTransform, interactive: bool) {
+        let cut_rect = to_screen.transform_rect(self.rect);
+        let painter = ui.painter().with_clip_rect(response.rect);
+
+        // Everything that is cut away is dimmed.
+        let shade = Color32::from_black_alpha(120);
+        let canvas = to_screen.transform_rect(self.bounds);
+        painter.rect_filled(Rect::from_x_y_ranges(canvas.x_range(), canvas.top()..=cut_rect.top()), 0.0, shade);
+        painter.rect_filled(Rect::from_x_y_ranges(canvas.x_range(), cut_rect.bottom()..=canvas.bottom()), 0.0, shade);
+        painter.rect_filled(Rect::from_x_y_ranges(canvas.left()..=cut_rect.left(), cut_rect.y_range()), 0.0, shade);
+        painter.rect_filled(Rect::from_x_y_ranges(cut_rect.right()..=canvas.right(), cut_rect.y_range()), 0.0, shade);
+        painter.rect_stroke(cut_rect, 0.0, Stroke::new(2.0, Color32::GRAY));
+
+        // Rule of thirds.
+        let third = cut_rect.size() / 3.0;
+        for i in 1..3 {
+            let x = cut_rect.left() + third.x * i as f32;
+            let y = cut_rect.top() + third.y * i as f32;
+            let stroke = Stroke::new(1.0, Color32::DARK_GRAY);
+            painter.add(Shape::dashed_line(&[Pos2::new(x, cut_rect.top()), Pos2::new(x, cut_rect.bottom())], stroke, 2.5, 5.));
+            painter.add(Shape::dashed_line(&[Pos2::new(cut_rect.left(), y), Pos2::new(cut_rect.right(), y)], stroke, 2.5, 5.));
+        }
 
-        self.cut_rect.min.x = response.rect.min.x + (response.rect.width() * self.offset_x_left) as f32;
-        self.cut_rect.min.y = response.rect.min.y + (response.rect.height() * self.offset_y_up) as f32;
-        self.cut_rect.max.x = response.rect.max.x - (response.rect.width() * self.offset_x_right) as f32;
-        self.cut_rect.max.y = response.rect.max.y - (response.rect.height() * self.offset_y_down) as f32;
+        for corner in [cut_rect.left_top(), cut_rect.right_top(), cut_rect.right_bottom(), cut_rect.left_bottom()] {
+            let handle = Rect::from_center_size(corner, Vec2::splat(GRIP_DISTANCE));
+            painter.rect(handle, 0.0, Color32::WHITE, Stroke::new(1.0, Color32::DARK_GRAY));
+        }
 
+        if !interactive {
+            self.drag = None;
+            return;
+        }
+        let grip = |pointer: Pos2| grip_at(cut_rect, pointer);
+        if let Some(cursor) = ui.ctx().pointer_hover_pos().filter(|p| canvas.contains(*p)).and_then(grip).map(cursor_icon) {
+            ui.ctx().set_cursor_icon(cursor);
+        }
 
-        if self.cut_rect.is_positive() {
-            ui.painter().add(Shape::Rect(RectShape {
-                rect: self.cut_rect,
-                fill: Color32::from_rgba_unmultiplied(128, 128, 128, 20),
-                stroke: Stroke::new(3.0, Color32::GRAY),
-                rounding: Rounding::none()
-            }));
+        let from_screen = to_screen.inverse();
+        if response.drag_started() {
+            self.drag = response.interact_pointer_pos().and_then(|pointer| {
+                Some(Drag { grip: grip(pointer)?, start_rect: self.rect, start_pointer: from_screen * pointer })
+            });
+        }
+        if let (Some(drag), Some(pointer)) = (self.drag.clone(), response.interact_pointer_pos()) {
+            ui.ctx().set_cursor_icon(cursor_icon(drag.grip));
+            let pointer = from_screen * pointer;
+            self.rect = match drag.grip {
+                Grip::Move => translate_inside(drag.start_rect.translate(pointer - drag.start_pointer), self.bounds),
+                Grip::Resize { x, y } => resize(drag.start_rect, x, y, pointer, self.aspect_ratio.value(), self.bounds),
+            };
+        }
+        if response.drag_released() {
+            self.drag = None;
+        }
+    }
 
-            let xr = response.rect.min.x + (response.rect.width() * self.offset_x_left) as f32;
-            let yr = response.rect.min.y + (response.rect.height() * self.offset_y_up) as f32;
+    /// Arrow keys move the crop by one pixel, ten with Shift.
+    pub fn nudge(&mut self, ctx: &Context) {
+        let directions = [
+            (Key::ArrowLeft, Vec2::new(-1.0, 0.0)),
+            (Key::ArrowRight, Vec2::new(1.0, 0.0)),
+            (Key::ArrowUp, Vec2::new(0.0, -1.0)),
+            (Key::ArrowDown, Vec2::new(0.0, 1.0)),
+        ];
+        for (key, direction) in directions {
+            if ctx.input_mut(|i| i.consume_key(Modifiers::SHIFT, key)) {
+                self.rect = translate_inside(self.rect.translate(direction * 10.0), self.bounds);
+            }
+            if ctx.input_mut(|i| i.consume_key(Modifiers::NONE, key)) {
+                self.rect = translate_inside(self.rect.translate(direction), self.bounds);
+            }
+        }
+    }
 
-            let xthird = self.cut_rect.size().x / 3.0;
-            let ythird = self.cut_rect.size().y / 3.0;
+    /// Aspect ratio presets and the position and size of the crop, in pixels.
+    pub fn settings_ui(&mut self, ui: &mut Ui) {
+        let previous_ratio = self.aspect_ratio;
+        ComboBox::from_id_source("crop_aspect_ratio")
+            .selected_text(self.aspect_ratio.label())
+            .show_ui(ui, |ui| {
+                for ratio in [AspectRatio::Free, AspectRatio::Square, AspectRatio::FourThree, AspectRatio::SixteenNine] {
+                    ui.selectable_value(&mut self.aspect_ratio, ratio, ratio.label());
+                }
+                if !matches!(self.aspect_ratio, AspectRatio::Custom(..)) && ui.selectable_label(false, "Custom").clicked() {
+                    let (width, height) = (self.rect.width().round() as u32, self.rect.height().round() as u32);
+                    self.aspect_ratio = AspectRatio::Custom(width.max(1), height.max(1));
+                }
+            });
+        if let AspectRatio::Custom(width, height) = &mut self.aspect_ratio {
+            ui.add(DragValue::new(width).clamp_range(1..=9999));
+            ui.label(":");
+            ui.add(DragValue::new(height).clamp_range(1..=9999));
+        }
+        if self.aspect_ratio != previous_ratio {
+            self.rect = fit_ratio(self.rect, self.aspect_ratio.value(), self.bounds);
+        }
 
-            let point1thirdw = Pos2::new(xr + xthird, yr);
-            let point2thirdw = Pos2::new(xr + xthird * 2.0, yr);
+        let mut rect = self.area();
+        let (mut x, mut y, mut width, mut height) = (rect.min.x, rect.min.y, rect.width(), rect.height());
+        let max = self.bounds.max;
+        let min_size = min_size(self.bounds);
+        ui.label("x");
+        let x_changed = ui.add(DragValue::new(&mut x).clamp_range(0.0..=(max.x - MIN_SIZE).max(0.0))).changed();
+        ui.label("y");
+        let y_changed = ui.add(DragValue::new(&mut y).clamp_range(0.0..=(max.y - MIN_SIZE).max(0.0))).changed();
+        ui.label("w");
+        let width_changed = ui.add(DragValue::new(&mut width).clamp_range(min_size.x..=max.x).suffix(" px")).changed();
+        ui.label("h");
+        let height_changed = ui.add(DragValue::new(&mut height).clamp_range(min_size.y..=max.y).suffix(" px")).changed();
+
+        if x_changed || y_changed {
+            rect = translate_inside(Rect::from_min_size(Pos2::new(x, y), rect.size()), self.bounds);
+        }
+        if width_changed || height_changed {
+            let ratio = self.aspect_ratio.value();
+            let size = match ratio {
+                Some(ratio) if width_changed => Vec2::new(width, width / ratio),
+                Some(ratio) => Vec2::new(height * ratio, height),
+                None => Vec2::new(width, height),
+            };
+            rect = fit_ratio(Rect::from_min_size(rect.min, size), ratio, self.bounds);
+        }
+        if x_changed || y_changed || width_changed || height_changed {
+            self.rect = rect;
+        }
+    }
+}
 
-            let point1thirdh = Pos2::new(xr, yr + ythird);
-            let point2thirdh = Pos2::new(xr, yr + ythird * 2.0);
+/// What the pointer at `pointer` would grab of the crop shown in `cut_rect`, both in screen coordinates.
+fn grip_at(cut_rect: Rect, pointer: Pos2) -> Option<Grip> {
+    let near = |value: f32, edge: f32| (value - edge).abs() <= GRIP_DISTANCE;
+    if !cut_rect.expand(GRIP_DISTANCE).contains(pointer) {
+        return None;
+    }
+    let x = if near(pointer.x, cut_rect.left()) {
+        Some(Edge::Start)
+    } else if near(pointer.x, cut_rect.right()) {
+        Some(Edge::End)
+    } else {
+        None
+    };
+    let y = if near(pointer.y, cut_rect.top()) {
+        Some(Edge::Start)
+    } else if near(pointer.y, cut_rect.bottom()) {
+        Some(Edge::End)
+    } else {
+        None
+    };
+    match (x, y) {
+        (None, None) => cut_rect.contains(pointer).then_some(Grip::Move),
+        (x, y) => Some(Grip::Resize { x, y }),
+    }
+}
 
-            let point1thirdwh = Pos2::new(xr + xthird, yr + self.cut_rect.size().y);
-            let point2thirdwh = Pos2::new(xr + xthird * 2.0, yr + self.cut_rect.size().y);
+fn cursor_icon(grip: Grip) -> CursorIcon {
+    match grip {
+        Grip::Move => CursorIcon::Move,
+        Grip::Resize { x: Some(x), y: Some(y) } if x == y => CursorIcon::ResizeNwSe,
+        Grip::Resize { x: Some(_), y: Some(_) } => CursorIcon::ResizeNeSw,
+        Grip::Resize { x: Some(_), y: None } => CursorIcon::ResizeHorizontal,
+        Grip::Resize { .. } => CursorIcon::ResizeVertical,
+    }
+}
 
-            let point1thirdhw = Pos2::new(xr + self.cut_rect.size().x, yr + ythird);
-            let point2thirdhw = Pos2::new(xr + self.cut_rect.size().x, yr + ythird * 2.0);
+/// Smallest size of the crop: `MIN_SIZE`, or the whole side of images smaller than that.
+fn min_size(bounds: Rect) -> Vec2 {
+    Vec2::splat(MIN_SIZE).min(bounds.size()).max(Vec2::ZERO)
+}
 
-            let line1 = Shape::dashed_line(&[point1thirdw, point1thirdwh], Stroke::new(1.0, Color32::DARK_GRAY), 2.5, 5.);
-            let line2 = Shape::dashed_line(&[point2thirdw, point2thirdwh], Stroke::new(1.0, Color32::DARK_GRAY), 2.5, 5.);
-            let line3 = Shape::dashed_line(&[point1thirdh, point1thirdhw], Stroke::new(1.0, Color32::DARK_GRAY), 2.5, 5.);
-            let line4 = Shape::dashed_line(&[point2thirdh, point2thirdhw], Stroke::new(1.0, Color32::DARK_GRAY), 2.5, 5.);
+/// Moves `rect` back inside `bounds`, shrinking it first if it is larger.
+fn translate_inside(rect: Rect, bounds: Rect) -> Rect {
+    let size = rect.size().min(bounds.size()).max(Vec2::ZERO);
+    let min = rect.min.clamp(bounds.min, bounds.max - size);
+    Rect::from_min_size(min, size)
+}
 
-            ui.painter().add(line1);
-            ui.painter().add(line2);
-            ui.painter().add(line3);
-            ui.painter().add(line4);
-        }
+/// `start` with the grabbed sides moved to `pointer`, keeping `ratio` (width / height) if there is one.
+fn resize(start: Rect, x: Option<Edge>, y: Option<Edge>, pointer: Pos2, ratio: Option<f32>, bounds: Rect) -> Rect {
+    let pointer = pointer.clamp(bounds.min, bounds.max);
+    let min_size = min_size(bounds);
+    let mut rect = start;
+    match x {
+        Some(Edge::Start) => rect.min.x = pointer.x.min(start.max.x - min_size.x).max(bounds.min.x),
+        Some(Edge::End) => rect.max.x = pointer.x.max(start.min.x + min_size.x).min(bounds.max.x),
+        None => {}
+    }
+    match y {
+        Some(Edge::Start) => rect.min.y = pointer.y.min(start.max.y - min_size.y).max(bounds.min.y),
+        Some(Edge::End) => rect.max.y = pointer.y.max(start.min.y + min_size.y).min(bounds.max.y),
+        None => {}
+    }
+    let Some(ratio) = ratio else {
+        return rect;
+    };
+
+    // The side that does not follow the pointer is derived from the one that does.
+    let mut size = rect.size();
+    match (x, y) {
+        (Some(_), Some(_)) if size.x / size.y > ratio => size.x = size.y * ratio,
+        (Some(_), Some(_)) => size.y = size.x / ratio,
+        (Some(_), None) => size.y = size.x / ratio,
+        _ => size.x = size.y * ratio,
+    }
+    if size.x > bounds.width() || size.y > bounds.height() {
+        let scale = (bounds.width() / size.x).min(bounds.height() / size.y);
+        size *= scale;
+    }
+    // Opposite sides stay where they were, sides along the other axis stay centred.
+    let left = match x {
+        Some(Edge::Start) => start.max.x - size.x,
+        Some(Edge::End) => start.min.x,
+        None => start.center().x - size.x / 2.0,
+    };
+    let top = match y {
+        Some(Edge::Start) => start.max.y - size.y,
+        Some(Edge::End) => start.min.y,
+        None => start.center().y - size.y / 2.0,
+    };
+    translate_inside(Rect::from_min_size(Pos2::new(left, top), size), bounds)
+}
 
-        let ctx = ui.ctx();
-        
-        let bound = 10.;
-        let inner_rect = self.cut_rect.shrink(bound);
+/// Largest rectangle with `ratio` (width / height) inside `rect`, kept inside `bounds`.
+fn fit_ratio(rect: Rect, ratio: Option<f32>, bounds: Rect) -> Rect {
+    let rect = translate_inside(rect, bounds);
+    let Some(ratio) = ratio else {
+        return rect;
+    };
+    let mut size = rect.size();
+    if size.x / size.y > ratio {
+        size.x = size.y * ratio;
+    } else {
+        size.y = size.x / ratio;
+    }
+    Rect::from_center_size(rect.center(), size.max(Vec2::splat(1.0)))
+}
 
-        if let Some(p_hover) = ctx.pointer_hover_pos() {
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            if (p_hover.y <= self.cut_rect.min.y + bound && p_hover.y >= self.cut_rect.min.y - bound) || (p_hover.y <= self.cut_rect.max.y + bound && p_hover.y >= self.cut_rect.max.y - bound) {
-                ctx.set_cursor_icon(CursorIcon::ResizeVertical);
-            }
-            else if (p_hover.x <= self.cut_rect.min.x + bound && p_hover.x >= self.cut_rect.min.x - bound) || (p_hover.x <= self.cut_rect.max.x + bound && p_hover.x >= self.cut_rect.max.x - bound) {
-                ctx.set_cursor_icon(CursorIcon::ResizeEast);
-            } else if inner_rect.contains(p_hover) {
-                ctx.set_cursor_icon(CursorIcon::AllScroll);
-            }
-        }
-        if let Some(p_interact) = response.interact_pointer_pos() {
-            if (p_interact.x <= (dim.x + response.rect.min.x) && p_interact.x >= response.rect.min.x) && (p_interact.y <= (dim.y + response.rect.min.y) && p_interact.y >= response.rect.min.y) {
-                match self.side {
-                    Side::None => {
-                        if (p_interact.y - (response.rect.min.y + (response.rect.height() * self.offset_y_up))).abs() <= bound {
-                            ctx.set_cursor_icon(CursorIcon::ResizeVertical);
-                            self.side = Side::Up;
-                        } else if (p_interact.y - (response.rect.max.y - (response.rect.height() * self.offset_y_down))).abs() <= bound {
-                            ctx.set_cursor_icon(CursorIcon::ResizeVertical);
-                            self.side = Side::Down;
-                        } else if (p_interact.x - (response.rect.max.x - (response.rect.width() * self.offset_x_right))).abs() <= bound {
-                            ctx.set_cursor_icon(CursorIcon::ResizeWest);
-                            self.side = Side::Right;
-                        } else if (p_interact.x - (response.rect.min.x + (response.rect.width() * self.offset_x_left))).abs() <= bound {
-                            ctx.set_cursor_icon(CursorIcon::ResizeEast);
-                            self.side = Side::Left;
-                        } else if inner_rect.contains(p_interact) {
-                            ctx.set_cursor_icon(CursorIcon::AllScroll);
-                            self.side = Side::Center;
-                        }
-                    }
-                    Side::Up => {
-                        if p_interact != self.last_click {
-                            let tmp = compute_offset(response.rect.min, p_interact).y / response.rect.height();
-                            if (self.cut_rect.max.y - self.cut_rect.min.y) >= 50. || (self.limit_reached == true && tmp < self.offset_y_up) {
-                                self.offset_y_up = tmp;
-                                self.limit_reached = false;
-                            } else {
-                                self.limit_reached = true;
-                            }
-                            self.last_click = p_interact;
-                        }
-                    }
-                    Side::Down => {
-                        if p_interact != self.last_click {
-                            let tmp = compute_offset(response.rect.max, p_interact).y / response.rect.height();
-
-                            if (self.cut_rect.max.y - self.cut_rect.min.y) >= 50. || (self.limit_reached == true && tmp < self.offset_y_down) {
-                                self.offset_y_down = tmp;
-                                self.limit_reached = false;
-                            } else {
-                                self.limit_reached = true;
-                            }
-                            self.last_click = p_interact;
-                        }
-                    }
-                    Side::Right => {
-                        if p_interact != self.last_click {
-                            let tmp = compute_offset(response.rect.max, p_interact).x / response.rect.width();
-                            if (self.cut_rect.max.x - self.cut_rect.min.x) >= 50. || (self.limit_reached == true && tmp < self.offset_x_right) {
-                                self.offset_x_right = tmp;
-                                self.limit_reached = false;
-                            } else {
-                                self.limit_reached = true;
-                            }
-                            self.last_click = p_interact;
-                        }
-                    }
-                    Side::Left => {
-                        if p_interact != self.last_click {
-                            let tmp = compute_offset(response.rect.min, p_interact).x / response.rect.width();
-                            if (self.cut_rect.max.x - self.cut_rect.min.x) >= 50. || (self.limit_reached == true && tmp < self.offset_x_left) {
-                                self.offset_x_left = tmp;
-                                self.limit_reached = false;
-                            } else {
-                                self.limit_reached = true;
-                            }
-                            self.last_click = p_interact;
-                        }
-                    }
-                    Side::Center => {
-                        if p_interact != self.last_click {
-                            if p_interact.x < self.last_click.x {
-                                let tmp = compute_offset(self.last_click, p_interact).x / response.rect.width();
-
-                                if (response.rect.max.x - (response.rect.width() * (self.offset_x_right + tmp))) <= (dim.x + response.rect.min.x) && (response.rect.min.x + (response.rect.width() * (self.offset_x_left - tmp))) >= response.rect.min.x {
-                                    self.offset_x_left -= tmp;
-                                    self.offset_x_right += tmp;
-                                }
-                            }
-
-                            if p_interact.x > self.last_click.x {
-                                let tmp = compute_offset(self.last_click, p_interact).x / response.rect.width();
-
-                                if (response.rect.max.x - (response.rect.width() * (self.offset_x_right - tmp))) <= (dim.x + response.rect.min.x) && (response.rect.min.x + (response.rect.width() * (self.offset_x_left + tmp))) >= response.rect.min.x {
-                                    self.offset_x_left += tmp;
-                                    self.offset_x_right -= tmp;
-                                }
-                            }
-
-                            if p_interact.y < self.last_click.y {
-                                let tmp = compute_offset(self.last_click, p_interact).y / response.rect.height();
-
-                                if (response.rect.max.y - (response.rect.height() * (self.offset_y_down + tmp))) <= (dim.y + response.rect.min.y) && (response.rect.min.y + (response.rect.height() * (self.offset_y_up - tmp))) >= response.rect.min.y {
-                                    self.offset_y_up -= tmp;
-                                    self.offset_y_down += tmp;
-                                }
-                            }
-
-                            if p_interact.y > self.last_click.y {
-                                let tmp = compute_offset(self.last_click, p_interact).y / response.rect.height();
-
-                                if (response.rect.max.y - (response.rect.height() * (self.offset_y_down - tmp))) <= (dim.y + response.rect.min.y) && (response.rect.min.y + (response.rect.height() * (self.offset_y_up + tmp))) >= response.rect.min.y {
-                                    self.offset_y_up += tmp;
-                                    self.offset_y_down -= tmp;
-                                }
-                            }
-
-                            self.last_click = p_interact;
-                        }
-                    }
-                }
-            }
-            self.last_click = p_interact;
-        } else {
-            self.side = Side::None;
-        }
+    fn bounds(width: f32, height: f32) -> Rect {
+        Rect::from_min_size(Pos2::ZERO, Vec2::new(width, height))
     }
 
-    pub fn get_cut_rect(&mut self, originalsize: Vec2) -> Rect {
-        let initialpoint = Pos2::new(originalsize.x * self.offset_x_left, originalsize.y * self.offset_y_up);
-
-        let mut scaledsize = Vec2::ZERO;
+    #[test]
+    fn resize_keeps_the_minimum_size() {
+        let start = Rect::from_min_max(Pos2::new(10.0, 10.0), Pos2::new(50.0, 50.0));
+        let rect = resize(start, Some(Edge::End), Some(Edge::Start), Pos2::new(0.0, 100.0), None, bounds(100.0, 100.0));
+        assert_eq!(rect, Rect::from_min_max(Pos2::new(10.0, 42.0), Pos2::new(18.0, 50.0)));
+    }
 
-        scaledsize.y = originalsize.y - originalsize.y * (self.offset_y_down + self.offset_y_up);
-        scaledsize.x = originalsize.x - originalsize.x * (self.offset_x_left + self.offset_x_right);
+    #[test]
+    fn resize_stays_inside_images_smaller_than_the_minimum_size() {
+        let image = bounds(5.0, 3.0);
+        for (x, y) in [(Edge::Start, Edge::Start), (Edge::End, Edge::End), (Edge::Start, Edge::End)] {
+            for pointer in [Pos2::new(-10.0, -10.0), Pos2::new(2.0, 1.0), Pos2::new(10.0, 10.0)] {
+                let rect = resize(image, Some(x), Some(y), pointer, None, image);
+                assert!(image.contains_rect(rect), "{:?} is outside of the image", rect);
+                assert_eq!(rect, image);
+            }
+        }
+        let square = resize(image, Some(Edge::End), None, Pos2::new(1.0, 1.0), Some(1.0), image);
+        assert!(image.contains_rect(square), "{:?} is outside of the image", square);
+    }
 
+    #[test]
+    fn translate_inside_moves_and_shrinks() {
+        let image = bounds(100.0, 50.0);
+        let moved = translate_inside(Rect::from_min_size(Pos2::new(90.0, -5.0), Vec2::new(20.0, 10.0)), image);
+        assert_eq!(moved, Rect::from_min_size(Pos2::new(80.0, 0.0), Vec2::new(20.0, 10.0)));
+        let shrunk = translate_inside(Rect::from_min_size(Pos2::new(-10.0, 0.0), Vec2::new(200.0, 20.0)), image);
+        assert_eq!(shrunk, Rect::from_min_size(Pos2::ZERO, Vec2::new(100.0, 20.0)));
+        let tiny = bounds(3.0, 3.0);
+        assert_eq!(translate_inside(Rect::from_min_size(Pos2::new(1.0, 1.0), Vec2::splat(8.0)), tiny), tiny);
+    }
 
-        self.scaled_rect = Rect::from_min_size( initialpoint, scaledsize);
-        self.scaled_rect
+    #[test]
+    fn min_size_is_limited_by_the_image() {
+        assert_eq!(min_size(bounds(100.0, 100.0)), Vec2::splat(MIN_SIZE));
+        assert_eq!(min_size(bounds(5.0, 30.0)), Vec2::new(5.0, MIN_SIZE));
     }
-}
 
-fn compute_offset(standard: Pos2, current: Pos2) -> Pos2 { 
-    Pos2::new((standard.x - current.x).abs(), (standard.y - current.y).abs())
+    #[test]
+    fn grip_at_finds_sides_corners_and_the_inside() {
+        let crop = Rect::from_min_max(Pos2::new(100.0, 100.0), Pos2::new(300.0, 200.0));
+        assert!(matches!(grip_at(crop, Pos2::new(200.0, 150.0)), Some(Grip::Move)));
+        assert!(matches!(grip_at(crop, Pos2::new(95.0, 150.0)), Some(Grip::Resize { x: Some(Edge::Start), y: None })));
+        assert!(matches!(grip_at(crop, Pos2::new(305.0, 205.0)), Some(Grip::Resize { x: Some(Edge::End), y: Some(Edge::End) })));
+        assert!(grip_at(crop, Pos2::new(50.0, 150.0)).is_none());
+    }
 }
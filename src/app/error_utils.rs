@@ -0,0 +1,92 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::app::ImgFormats;
+
+/// Everything that can go wrong while capturing, saving or copying a screenshot.
+#[derive(Debug)]
+pub enum YasaError {
+    /// The platform refused to list or capture the displays (e.g. missing screen recording permission).
+    Capture(String),
+    NoDisplays,
+    DisplayNotFound(u32),
+    /// The requested area does not overlap any display.
+    EmptyArea,
+    Image(image::ImageError),
+    Io(std::io::Error),
+    InvalidFilename(String),
+    InvalidPath(PathBuf),
+    Clipboard(String),
+    InvalidKey(String),
+    /// The shortcut is already bound to the named action.
+    ShortcutInUse(String),
+    Settings(String),
+    /// A `.yasa` project that cannot be read.
+    Project(String),
+    /// The file is neither an image of a supported format nor a project.
+    CannotOpen(PathBuf),
+    /// A flat image was to be written in a format that needs the annotations (project, SVG) or pages (PDF).
+    NotAnImageFormat(ImgFormats),
+}
+
+impl fmt::Display for YasaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            YasaError::Capture(e) => write!(f, "screen capture failed: {}", e),
+            YasaError::NoDisplays => write!(f, "no display available to capture"),
+            YasaError::DisplayNotFound(id) => write!(f, "display {} not found", id),
+            YasaError::EmptyArea => write!(f, "the selected area is outside of every display"),
+            YasaError::Image(e) => write!(f, "image error: {}", e),
+            YasaError::Io(e) => write!(f, "{}", e),
+            YasaError::InvalidFilename(name) => write!(f, "'{}' is not a valid file name", name),
+            YasaError::InvalidPath(path) => write!(f, "{} is not a valid folder", path.display()),
+            YasaError::Clipboard(e) => write!(f, "clipboard error: {}", e),
+            YasaError::InvalidKey(key) => write!(f, "'{}' is not a supported key", key),
+            YasaError::ShortcutInUse(action) => write!(f, "shortcut already in use by action '{}'", action),
+            YasaError::Settings(e) => write!(f, "settings could not be loaded or saved: {}", e),
+            YasaError::Project(e) => write!(f, "invalid project: {}", e),
+            YasaError::CannotOpen(path) => write!(f, "{} is not a PNG, JPEG or GIF image or a .yasa project", path.display()),
+            YasaError::NotAnImageFormat(format) => write!(f, "{:?} is not an image format", format),
+        }
+    }
+}
+
+impl std::error::Error for YasaError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            YasaError::Image(e) => Some(e),
+            YasaError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<image::ImageError> for YasaError {
+    fn from(e: image::ImageError) -> Self {
+        match e {
+            image::ImageError::IoError(e) => YasaError::Io(e),
+            e => YasaError::Image(e),
+        }
+    }
+}
+
+impl From<std::io::Error> for YasaError {
+    fn from(e: std::io::Error) -> Self {
+        YasaError::Io(e)
+    }
+}
+
+impl From<zip::result::ZipError> for YasaError {
+    fn from(e: zip::result::ZipError) -> Self {
+        match e {
+            zip::result::ZipError::Io(e) => YasaError::Io(e),
+            e => YasaError::Project(e.to_string()),
+        }
+    }
+}
+
+impl From<arboard::Error> for YasaError {
+    fn from(e: arboard::Error) -> Self {
+        YasaError::Clipboard(e.to_string())
+    }
+}
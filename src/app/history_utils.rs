@@ -0,0 +1,215 @@
+use super::image_utils::PixelArea;
+use super::painting_utils::DrawObj;
+
+/// One edit of the screenshot that can be undone and redone.
+///
+/// The last element of the annotation list is the shape being drawn: commands only touch the ones before it.
+#[derive(Clone)]
+pub enum Command {
+    Add { index: usize, shape: DrawObj },
+    Remove { index: usize, shape: DrawObj },
+    /// The shape at `index` was moved, resized or restyled.
+    Edit { index: usize, before: DrawObj, after: DrawObj },
+    Clear { shapes: Vec<DrawObj> },
+    /// The kept part of the screenshot changed, `None` being all of it.
+    Crop { before: Option<PixelArea>, after: Option<PixelArea> },
+}
+
+impl Command {
+    /// Short description shown in the history list.
+    pub fn label(&self) -> String {
+        match self {
+            Command::Add { shape, .. } => format!("Add {}", shape.kind().label()),
+            Command::Remove { shape, .. } => format!("Delete {}", shape.kind().label()),
+            Command::Edit { after, .. } => format!("Edit {}", after.kind().label()),
+            Command::Clear { .. } => "Clear all".to_string(),
+            Command::Crop { after: Some((_, _, width, height)), .. } => format!("Crop to {}×{}", width, height),
+            Command::Crop { after: None, .. } => "Remove the crop".to_string(),
+        }
+    }
+
+    /// True if the command changes the size of the output image.
+    pub fn changes_crop(&self) -> bool {
+        matches!(self, Command::Crop { .. })
+    }
+
+    pub fn apply(&self, shapes: &mut Vec<DrawObj>, crop_area: &mut Option<PixelArea>) {
+        match self {
+            Command::Add { index, shape } => shapes.insert(*index, shape.clone()),
+            Command::Remove { index, .. } => {
+                shapes.remove(*index);
+            }
+            Command::Edit { index, after, .. } => shapes[*index] = after.clone(),
+            Command::Clear { shapes: cleared } => {
+                shapes.drain(..cleared.len());
+            }
+            Command::Crop { after, .. } => *crop_area = *after,
+        }
+    }
+
+    pub fn revert(&self, shapes: &mut Vec<DrawObj>, crop_area: &mut Option<PixelArea>) {
+        match self {
+            Command::Add { index, .. } => {
+                shapes.remove(*index);
+            }
+            Command::Remove { index, shape } => shapes.insert(*index, shape.clone()),
+            Command::Edit { index, before, .. } => shapes[*index] = before.clone(),
+            Command::Clear { shapes: cleared } => {
+                shapes.splice(0..0, cleared.iter().cloned());
+            }
+            Command::Crop { before, .. } => *crop_area = *before,
+        }
+    }
+}
+
+/// Every command done so far, and the undone ones that can still be redone.
+#[derive(Clone, Default)]
+pub struct History {
+    done: Vec<Command>,
+    /// The most recently undone command is the last one.
+    undone: Vec<Command>,
+}
+
+impl History {
+    /// Adds a command that has already been applied. Anything undone can no longer be redone.
+    pub fn record(&mut self, command: Command) {
+        self.done.push(command);
+        self.undone.clear();
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.done.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.undone.is_empty()
+    }
+
+    /// Moves the last command to the undone ones and returns it, for the caller to revert.
+    pub fn undo(&mut self) -> Option<&Command> {
+        let command = self.done.pop()?;
+        self.undone.push(command);
+        self.undone.last()
+    }
+
+    /// Moves the last undone command back to the done ones and returns it, for the caller to apply.
+    pub fn redo(&mut self) -> Option<&Command> {
+        let command = self.undone.pop()?;
+        self.done.push(command);
+        self.done.last()
+    }
+
+    /// Number of commands currently applied.
+    pub fn position(&self) -> usize {
+        self.done.len()
+    }
+
+    /// Every command in the order it was made, undone ones included.
+    pub fn commands(&self) -> impl Iterator<Item = &Command> {
+        self.done.iter().chain(self.undone.iter().rev())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Rectangle from (`x`, `x`) to (`x + 10`, `x + 10`).
+    fn rectangle(x: f32) -> DrawObj {
+        serde_json::from_value(json!({
+            "points": [{ "x": x, "y": x }, { "x": x + 10.0, "y": x + 10.0 }],
+            "stroke": { "width": 2.0, "color": [0, 0, 255, 255] },
+            "shape": "Rectangle", "filled": false, "text": null, "redaction": null,
+        }))
+        .unwrap()
+    }
+
+    /// Two annotations followed by the shape being drawn.
+    fn shapes() -> Vec<DrawObj> {
+        vec![rectangle(1.0), rectangle(2.0), DrawObj::default()]
+    }
+
+    /// Applies `command` to `shapes` and `crop_area`, then checks that undoing and redoing it gives
+    /// back the same annotations and crop each time.
+    fn round_trip(command: Command, mut shapes: Vec<DrawObj>, mut crop_area: Option<PixelArea>) {
+        let (shapes_before, crop_before) = (shapes.clone(), crop_area);
+        command.apply(&mut shapes, &mut crop_area);
+        let (shapes_after, crop_after) = (shapes.clone(), crop_area);
+        assert!(shapes_after != shapes_before || crop_after != crop_before, "{} changed nothing", command.label());
+
+        command.revert(&mut shapes, &mut crop_area);
+        assert!(shapes == shapes_before, "{} was not reverted", command.label());
+        assert_eq!(crop_area, crop_before);
+
+        command.apply(&mut shapes, &mut crop_area);
+        assert!(shapes == shapes_after, "{} was not applied again", command.label());
+        assert_eq!(crop_area, crop_after);
+    }
+
+    #[test]
+    fn add_round_trip() {
+        round_trip(Command::Add { index: 1, shape: rectangle(3.0) }, shapes(), None);
+    }
+
+    #[test]
+    fn remove_round_trip() {
+        round_trip(Command::Remove { index: 0, shape: rectangle(1.0) }, shapes(), None);
+    }
+
+    #[test]
+    fn edit_round_trip() {
+        round_trip(Command::Edit { index: 1, before: rectangle(2.0), after: rectangle(5.0) }, shapes(), None);
+    }
+
+    #[test]
+    fn clear_round_trip() {
+        let mut shapes = shapes();
+        let cleared = shapes[..2].to_vec();
+        round_trip(Command::Clear { shapes: cleared }, shapes.clone(), None);
+
+        // The shape being drawn survives a clear.
+        Command::Clear { shapes: shapes[..2].to_vec() }.apply(&mut shapes, &mut None);
+        assert_eq!(shapes.len(), 1);
+    }
+
+    #[test]
+    fn crop_round_trip() {
+        round_trip(Command::Crop { before: None, after: Some((2, 3, 20, 10)) }, shapes(), None);
+        round_trip(Command::Crop { before: Some((2, 3, 20, 10)), after: Some((0, 0, 5, 5)) }, shapes(), Some((2, 3, 20, 10)));
+        round_trip(Command::Crop { before: Some((0, 0, 5, 5)), after: None }, shapes(), Some((0, 0, 5, 5)));
+    }
+
+    #[test]
+    fn undo_and_redo_walk_the_history() {
+        let mut history = History::default();
+        assert!(!history.can_undo() && !history.can_redo());
+        history.record(Command::Crop { before: None, after: Some((0, 0, 5, 5)) });
+        history.record(Command::Clear { shapes: vec![] });
+
+        assert_eq!(history.undo().map(Command::label).as_deref(), Some("Clear all"));
+        assert_eq!(history.position(), 1);
+        assert!(history.can_redo());
+        assert_eq!(history.commands().count(), 2);
+        assert_eq!(history.redo().map(Command::label).as_deref(), Some("Clear all"));
+        assert!(!history.can_redo());
+        assert!(history.redo().is_none());
+    }
+
+    #[test]
+    fn record_drops_the_undone_commands() {
+        let mut history = History::default();
+        history.record(Command::Crop { before: None, after: Some((0, 0, 5, 5)) });
+        history.record(Command::Crop { before: Some((0, 0, 5, 5)), after: Some((1, 1, 2, 2)) });
+        history.undo();
+        history.undo();
+        assert!(history.can_redo());
+
+        history.record(Command::Add { index: 0, shape: rectangle(1.0) });
+        assert!(!history.can_redo());
+        assert!(history.redo().is_none());
+        assert_eq!(history.position(), 1);
+        let labels: Vec<String> = history.commands().map(Command::label).collect();
+        assert_eq!(labels, vec!["Add rectangle".to_string()]);
+    }
+}
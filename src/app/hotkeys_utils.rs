@@ -1,121 +1,423 @@
-
+use super::error_utils::YasaError;
 use egui::{Key, KeyboardShortcut, Modifiers};
+use std::collections::BTreeMap;
+
+/// Every action that can be bound to keyboard shortcuts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Action {
+    Save,
+    CopyToClipboard,
+    Paste,
+    TakeScreenshot,
+    OpenFile,
+    PenTool,
+    StraightLineTool,
+    RectangleTool,
+    EllipseTool,
+    ArrowTool,
+    TextTool,
+    RedactionTool,
+    HighlighterTool,
+    SelectTool,
+    DeleteSelected,
+    CropTool,
+    Undo,
+    Redo,
+    StrokeWidthUp,
+    StrokeWidthDown,
+    LeaveCrop,
+}
 
-#[derive(Debug, Copy, Clone)]
+impl Action {
+    /// All actions, in the order they are listed in the settings table.
+    pub const ALL: [Action; 21] = [
+        Action::Save,
+        Action::CopyToClipboard,
+        Action::Paste,
+        Action::TakeScreenshot,
+        Action::OpenFile,
+        Action::PenTool,
+        Action::StraightLineTool,
+        Action::RectangleTool,
+        Action::EllipseTool,
+        Action::ArrowTool,
+        Action::TextTool,
+        Action::RedactionTool,
+        Action::HighlighterTool,
+        Action::SelectTool,
+        Action::DeleteSelected,
+        Action::CropTool,
+        Action::Undo,
+        Action::Redo,
+        Action::StrokeWidthUp,
+        Action::StrokeWidthDown,
+        Action::LeaveCrop,
+    ];
+
+    /// Identifier used in the config file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Save => "save",
+            Action::CopyToClipboard => "copy_to_clipboard",
+            Action::Paste => "paste",
+            Action::TakeScreenshot => "take_screenshot",
+            Action::OpenFile => "open_file",
+            Action::PenTool => "pen_tool",
+            Action::StraightLineTool => "straight_line_tool",
+            Action::RectangleTool => "rectangle_tool",
+            Action::EllipseTool => "ellipse_tool",
+            Action::ArrowTool => "arrow_tool",
+            Action::TextTool => "text_tool",
+            Action::RedactionTool => "redaction_tool",
+            Action::HighlighterTool => "highlighter_tool",
+            Action::SelectTool => "select_tool",
+            Action::DeleteSelected => "delete_selected",
+            Action::CropTool => "crop_tool",
+            Action::Undo => "undo",
+            Action::Redo => "redo",
+            Action::StrokeWidthUp => "stroke_width_up",
+            Action::StrokeWidthDown => "stroke_width_down",
+            Action::LeaveCrop => "leave_crop",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::Save => "Save image",
+            Action::CopyToClipboard => "Copy image to clipboard",
+            Action::Paste => "Paste an image from the clipboard",
+            Action::TakeScreenshot => "Take a screenshot",
+            Action::OpenFile => "Open an image or a project",
+            Action::PenTool => "Pen",
+            Action::StraightLineTool => "Straight line",
+            Action::RectangleTool => "Rectangle",
+            Action::EllipseTool => "Ellipse",
+            Action::ArrowTool => "Arrow",
+            Action::TextTool => "Text",
+            Action::RedactionTool => "Redact (pixelate or blur)",
+            Action::HighlighterTool => "Highlighter",
+            Action::SelectTool => "Select, move and resize",
+            Action::DeleteSelected => "Delete the selected shape",
+            Action::CropTool => "Crop",
+            Action::Undo => "Undo",
+            Action::Redo => "Redo",
+            Action::StrokeWidthUp => "Increase stroke width",
+            Action::StrokeWidthDown => "Decrease stroke width",
+            Action::LeaveCrop => "Leave crop",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.into_iter().find(|action| action.name() == name)
+    }
+
+    fn default_shortcuts(self) -> Vec<KeyboardShortcut> {
+        match self {
+            Action::Save => vec![KeyboardShortcut::new(Modifiers::CTRL, Key::S)],
+            Action::CopyToClipboard => vec![KeyboardShortcut::new(Modifiers::CTRL, Key::C)],
+            Action::Paste => vec![KeyboardShortcut::new(Modifiers::CTRL, Key::V)],
+            Action::TakeScreenshot => vec![KeyboardShortcut::new(Modifiers::CTRL, Key::D)],
+            Action::OpenFile => vec![KeyboardShortcut::new(Modifiers::CTRL, Key::O)],
+            Action::PenTool => vec![KeyboardShortcut::new(Modifiers::NONE, Key::P)],
+            Action::StraightLineTool => vec![KeyboardShortcut::new(Modifiers::NONE, Key::L)],
+            Action::RectangleTool => vec![KeyboardShortcut::new(Modifiers::NONE, Key::R)],
+            Action::EllipseTool => vec![KeyboardShortcut::new(Modifiers::NONE, Key::E)],
+            Action::ArrowTool => vec![KeyboardShortcut::new(Modifiers::NONE, Key::A)],
+            Action::TextTool => vec![KeyboardShortcut::new(Modifiers::NONE, Key::T)],
+            Action::RedactionTool => vec![KeyboardShortcut::new(Modifiers::NONE, Key::X)],
+            Action::HighlighterTool => vec![KeyboardShortcut::new(Modifiers::NONE, Key::H)],
+            Action::SelectTool => vec![KeyboardShortcut::new(Modifiers::NONE, Key::V)],
+            Action::DeleteSelected => vec![
+                KeyboardShortcut::new(Modifiers::NONE, Key::Delete),
+                KeyboardShortcut::new(Modifiers::NONE, Key::Backspace),
+            ],
+            Action::CropTool => vec![KeyboardShortcut::new(Modifiers::NONE, Key::C)],
+            Action::Undo => vec![KeyboardShortcut::new(Modifiers::CTRL, Key::Z)],
+            Action::Redo => vec![KeyboardShortcut::new(Modifiers::CTRL | Modifiers::SHIFT, Key::Z)],
+            Action::StrokeWidthUp => vec![KeyboardShortcut::new(Modifiers::NONE, Key::PlusEquals)],
+            Action::StrokeWidthDown => vec![KeyboardShortcut::new(Modifiers::NONE, Key::Minus)],
+            Action::LeaveCrop => vec![KeyboardShortcut::new(Modifiers::NONE, Key::Escape)],
+        }
+    }
+}
+
+/// Shortcut registry: each action is bound to zero or more keyboard shortcuts.
+#[derive(Debug, Clone, PartialEq)]
 pub struct AllKeyboardShortcuts {
-    pub save: Option<KeyboardShortcut>,
-    pub copy_to_clipboard: Option<KeyboardShortcut>,
-    pub test: Option<KeyboardShortcut>,
-    pub take_screenshot: Option<KeyboardShortcut>,
+    bindings: BTreeMap<Action, Vec<KeyboardShortcut>>,
 }
 
 impl Default for AllKeyboardShortcuts {
     fn default() -> Self {
         Self {
-            save: Some(KeyboardShortcut::new(Modifiers::CTRL, Key::S)),
-            copy_to_clipboard: Some(KeyboardShortcut::new(Modifiers::CTRL, Key::C)),
-            test: Some(KeyboardShortcut::new(Modifiers::CTRL, Key::T)),
-            take_screenshot: Some(KeyboardShortcut::new(Modifiers::CTRL, Key::D)),
+            bindings: Action::ALL
+                .into_iter()
+                .map(|action| (action, action.default_shortcuts()))
+                .collect(),
         }
     }
 }
 
 impl AllKeyboardShortcuts {
-    pub fn update_keyboard_shortcut(&mut self, field: &str, new_shortcut: KeyboardShortcut) {
-        match field {
-            "save" => self.save = Some(new_shortcut),
-            "copy_to_clipboard" => self.copy_to_clipboard = Some(new_shortcut),
-            "test" => self.test = Some(new_shortcut),
-            "take_screenshot" => self.take_screenshot = Some(new_shortcut),
-            _ => panic!("Invalid field name"),
-        };
-    }
-
-    pub fn check_if_valid(&self, shortcut: &KeyboardShortcut) -> (bool, String) {
-        if shortcut.eq(self.save.as_ref().unwrap()) {
-            return (false, "save".to_string());
-        } else if shortcut.eq(self.copy_to_clipboard.as_ref().unwrap()) {
-            return (false, "copy_to_clipboard".to_string());
-        } else if shortcut.eq(self.test.as_ref().unwrap()) {
-            return (false, "test".to_string());
-        } else if shortcut.eq(self.take_screenshot.as_ref().unwrap()) {
-            return (false, "take_screenshot".to_string());
+    pub fn shortcuts(&self, action: Action) -> &[KeyboardShortcut] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// True if one of the shortcuts of `action` was pressed this frame. The key press is consumed.
+    pub fn consume(&self, ctx: &egui::Context, action: Action) -> bool {
+        ctx.input_mut(|i| self.shortcuts(action).iter().any(|shortcut| i.consume_shortcut(shortcut)))
+    }
+
+    /// The action already using `shortcut`, if any.
+    pub fn conflict(&self, shortcut: &KeyboardShortcut) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(_, shortcuts)| shortcuts.contains(shortcut))
+            .map(|(action, _)| *action)
+    }
+
+    /// Binds `shortcut` to `action` in addition to its current shortcuts.
+    pub fn add(&mut self, action: Action, shortcut: KeyboardShortcut) -> Result<(), YasaError> {
+        if let Some(other) = self.conflict(&shortcut) {
+            return Err(YasaError::ShortcutInUse(other.label().to_string()));
         }
+        self.bindings.entry(action).or_default().push(shortcut);
+        Ok(())
+    }
 
-        return (true, "none".to_string());
+    /// Makes `shortcut` the only shortcut of `action`.
+    pub fn replace(&mut self, action: Action, shortcut: KeyboardShortcut) -> Result<(), YasaError> {
+        match self.conflict(&shortcut) {
+            Some(other) if other != action => Err(YasaError::ShortcutInUse(other.label().to_string())),
+            _ => {
+                self.bindings.insert(action, vec![shortcut]);
+                Ok(())
+            }
+        }
     }
 
-    pub fn human_readable_shorcut(&self, field: &str) -> String {
-        let shortcut = match field {
-            "save" => self.save,
-            "copy_to_clipboard" => self.copy_to_clipboard,
-            "test" => self.test,
-            "take_screenshot" => self.take_screenshot,
-            _ => panic!("Invalid field name"),
-        };
+    /// Restores the default shortcuts of `action`, unless they are now used by another action.
+    pub fn reset(&mut self, action: Action) -> Result<(), YasaError> {
+        let defaults = action.default_shortcuts();
+        for shortcut in &defaults {
+            match self.conflict(shortcut) {
+                Some(other) if other != action => return Err(YasaError::ShortcutInUse(other.label().to_string())),
+                _ => (),
+            }
+        }
+        self.bindings.insert(action, defaults);
+        Ok(())
+    }
 
-        let mut readable_shortcut = "".to_string();
+    pub fn human_readable_shorcut(&self, action: Action) -> String {
+        let shortcuts = self.shortcuts(action);
+        if shortcuts.is_empty() {
+            return "None".to_string();
+        }
+        shortcuts.iter().map(format_shortcut).collect::<Vec<String>>().join(", ")
+    }
+
+    /// Shortcuts as `action name -> ["CTRL+S", ...]`, for the config file.
+    pub fn to_map(&self) -> BTreeMap<String, Vec<String>> {
+        self.bindings
+            .iter()
+            .map(|(action, shortcuts)| (action.name().to_string(), shortcuts.iter().map(format_shortcut).collect()))
+            .collect()
+    }
 
-        if shortcut.unwrap().modifiers.ctrl {
-            readable_shortcut.push_str("CTRL+"); 
+    /// Rebuilds the registry from the config file. Actions missing from the file keep their defaults,
+    /// while unknown actions, invalid shortcuts and conflicting ones are skipped.
+    pub fn from_map(map: &BTreeMap<String, Vec<String>>) -> Self {
+        let mut shortcuts = Self::default();
+        let configured: Vec<(Action, &Vec<String>)> = map
+            .iter()
+            .filter_map(|(name, texts)| Some((Action::from_name(name)?, texts)))
+            .collect();
+        for (action, _) in &configured {
+            shortcuts.bindings.insert(*action, vec![]);
         }
+        for (action, texts) in configured {
+            for shortcut in texts.iter().filter_map(|text| parse_shortcut(text).ok()) {
+                let _ = shortcuts.add(action, shortcut);
+            }
+        }
+        shortcuts
+    }
+}
+
+/// Formats a shortcut as e.g. "CTRL+SHIFT+S".
+pub fn format_shortcut(shortcut: &KeyboardShortcut) -> String {
+    let mut readable_shortcut = "".to_string();
 
-        if shortcut.unwrap().modifiers.alt {
-            readable_shortcut.push_str("ALT+");
+    if shortcut.modifiers.ctrl {
+        readable_shortcut.push_str("CTRL+");
+    }
+
+    if shortcut.modifiers.alt {
+        readable_shortcut.push_str("ALT+");
+    }
+
+    if shortcut.modifiers.shift {
+        readable_shortcut.push_str("SHIFT+");
+    }
+
+    readable_shortcut.push_str(shortcut.key.name());
+
+    readable_shortcut
+}
+
+/// Parses the format produced by `format_shortcut`. Modifiers are case insensitive and can be combined.
+pub fn parse_shortcut(text: &str) -> Result<KeyboardShortcut, YasaError> {
+    let mut parts: Vec<&str> = text.split('+').map(str::trim).collect();
+    let key = from_name(parts.pop().unwrap_or_default())?;
+    let mut modifiers = Modifiers::NONE;
+    for part in parts {
+        modifiers = modifiers
+            | match part.to_ascii_uppercase().as_str() {
+                "CTRL" | "CONTROL" => Modifiers::CTRL,
+                "ALT" => Modifiers::ALT,
+                "SHIFT" => Modifiers::SHIFT,
+                _ => return Err(YasaError::InvalidKey(text.to_string())),
+            };
+    }
+    Ok(KeyboardShortcut::new(modifiers, key))
+}
+
+/// Every key that can be used in a shortcut.
+const ALL_KEYS: [Key; 73] = [
+    Key::A, Key::B, Key::C, Key::D, Key::E, Key::F, Key::G, Key::H, Key::I, Key::J, Key::K, Key::L, Key::M,
+    Key::N, Key::O, Key::P, Key::Q, Key::R, Key::S, Key::T, Key::U, Key::V, Key::W, Key::X, Key::Y, Key::Z,
+    Key::Num0, Key::Num1, Key::Num2, Key::Num3, Key::Num4, Key::Num5, Key::Num6, Key::Num7, Key::Num8, Key::Num9,
+    Key::F1, Key::F2, Key::F3, Key::F4, Key::F5, Key::F6, Key::F7, Key::F8, Key::F9, Key::F10,
+    Key::F11, Key::F12, Key::F13, Key::F14, Key::F15, Key::F16, Key::F17, Key::F18, Key::F19, Key::F20,
+    Key::ArrowDown, Key::ArrowLeft, Key::ArrowRight, Key::ArrowUp,
+    Key::Escape, Key::Tab, Key::Backspace, Key::Enter, Key::Space, Key::Insert, Key::Delete,
+    Key::Home, Key::End, Key::PageUp, Key::PageDown,
+    Key::Minus, Key::PlusEquals,
+];
+
+/// Looks a key up by its egui name ("S", "5", "F5", "Up", "Minus", "Plus", ...), ignoring case.
+pub fn from_name(key: &str) -> Result<Key, YasaError> {
+    let name = key.trim();
+    let found = match name {
+        "-" => Some(Key::Minus),
+        "=" => Some(Key::PlusEquals),
+        _ => ALL_KEYS.into_iter().find(|k| k.name().eq_ignore_ascii_case(name)),
+    };
+    found.ok_or_else(|| YasaError::InvalidKey(key.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shortcut(modifiers: Modifiers, key: Key) -> KeyboardShortcut {
+        KeyboardShortcut::new(modifiers, key)
+    }
+
+    fn in_use_by(result: Result<(), YasaError>) -> String {
+        match result {
+            Err(YasaError::ShortcutInUse(action)) => action,
+            other => panic!("expected a conflict, got {:?}", other),
         }
+    }
 
-        if shortcut.unwrap().modifiers.shift {
-            readable_shortcut.push_str("SHIFT+");
+    #[test]
+    fn shortcuts_round_trip_through_text() {
+        let shortcuts = [
+            shortcut(Modifiers::CTRL | Modifiers::SHIFT, Key::S),
+            shortcut(Modifiers::CTRL | Modifiers::ALT | Modifiers::SHIFT, Key::Z),
+            shortcut(Modifiers::NONE, Key::F5),
+            shortcut(Modifiers::ALT, Key::F12),
+            shortcut(Modifiers::NONE, Key::ArrowUp),
+            shortcut(Modifiers::SHIFT, Key::ArrowLeft),
+            shortcut(Modifiers::NONE, Key::Num7),
+            shortcut(Modifiers::CTRL, Key::Minus),
+        ];
+        for shortcut in shortcuts {
+            let text = format_shortcut(&shortcut);
+            assert_eq!(parse_shortcut(&text).unwrap(), shortcut, "{}", text);
         }
+        assert_eq!(format_shortcut(&shortcut(Modifiers::CTRL | Modifiers::SHIFT, Key::S)), "CTRL+SHIFT+S");
+    }
+
+    #[test]
+    fn parse_shortcut_is_lenient_about_case_and_spaces() {
+        assert_eq!(parse_shortcut("ctrl + shift + s").unwrap(), shortcut(Modifiers::CTRL | Modifiers::SHIFT, Key::S));
+        assert_eq!(parse_shortcut("Control+f5").unwrap(), shortcut(Modifiers::CTRL, Key::F5));
+        assert_eq!(parse_shortcut("-").unwrap(), shortcut(Modifiers::NONE, Key::Minus));
+    }
 
-        readable_shortcut.push_str(shortcut.unwrap().key.name());
-
-        return readable_shortcut;
-    }
-
-    pub fn from_name(self, key: &str) -> Key {
-        
-        let mut_key = key.to_ascii_uppercase();
-
-        match mut_key.as_str() {
-            "A" => Key::A,
-            "B" => Key::B,
-            "C" => Key::C,
-            "D" => Key::D,
-            "E" => Key::E,
-            "F" => Key::F,
-            "G" => Key::G,
-            "H" => Key::H,
-            "I" => Key::I,
-            "J" => Key::J,
-            "K" => Key::K,
-            "L" => Key::L,
-            "M" => Key::M,
-            "N" => Key::N,
-            "O" => Key::O,
-            "P" => Key::P,
-            "Q" => Key::Q,
-            "R" => Key::R,
-            "S" => Key::S,
-            "T" => Key::T,
-            "U" => Key::U,
-            "V" => Key::V,
-            "W" => Key::W,
-            "X" => Key::X,
-            "Y" => Key::Y,
-            "Z" => Key::Z,
-            "0" => Key::Num0,
-            "1" => Key::Num1,
-            "2" => Key::Num2,
-            "3" => Key::Num3,
-            "4" => Key::Num4,
-            "5" => Key::Num5,
-            "6" => Key::Num6,
-            "7" => Key::Num7,
-            "8" => Key::Num8,
-            "9" => Key::Num9,
-            _ => panic!("Invalid key"),
+    #[test]
+    fn parse_shortcut_rejects_unknown_keys_and_modifiers() {
+        for text in ["", "CTRL+", "CTRL+SS", "SUPER+S", "CTRL+F99", "S+CTRL", "+"] {
+            assert!(matches!(parse_shortcut(text), Err(YasaError::InvalidKey(_))), "{:?} was accepted", text);
         }
     }
 
+    #[test]
+    fn add_keeps_the_other_shortcuts_and_names_the_conflict() {
+        let mut shortcuts = AllKeyboardShortcuts::default();
+        shortcuts.add(Action::Save, shortcut(Modifiers::CTRL | Modifiers::SHIFT, Key::S)).unwrap();
+        assert_eq!(shortcuts.shortcuts(Action::Save).len(), 2);
+
+        let taken = shortcut(Modifiers::CTRL, Key::Z);
+        assert_eq!(in_use_by(shortcuts.add(Action::Save, taken)), Action::Undo.label());
+        // Even the action's own shortcut is not added twice.
+        assert_eq!(in_use_by(shortcuts.add(Action::Save, shortcut(Modifiers::CTRL, Key::S))), Action::Save.label());
+        assert_eq!(shortcuts.shortcuts(Action::Save).len(), 2);
+    }
+
+    #[test]
+    fn replace_binds_a_single_shortcut() {
+        let mut shortcuts = AllKeyboardShortcuts::default();
+        shortcuts.replace(Action::DeleteSelected, shortcut(Modifiers::NONE, Key::D)).unwrap();
+        assert_eq!(shortcuts.shortcuts(Action::DeleteSelected), [shortcut(Modifiers::NONE, Key::D)]);
+        assert_eq!(shortcuts.conflict(&shortcut(Modifiers::NONE, Key::Delete)), None);
+
+        // Its own shortcut is not a conflict.
+        shortcuts.replace(Action::DeleteSelected, shortcut(Modifiers::NONE, Key::D)).unwrap();
+        let result = shortcuts.replace(Action::Redo, shortcut(Modifiers::NONE, Key::P));
+        assert_eq!(in_use_by(result), Action::PenTool.label());
+        assert_eq!(shortcuts.shortcuts(Action::Redo), [shortcut(Modifiers::CTRL | Modifiers::SHIFT, Key::Z)]);
+    }
+
+    #[test]
+    fn reset_restores_the_defaults_unless_they_are_taken() {
+        let mut shortcuts = AllKeyboardShortcuts::default();
+        shortcuts.replace(Action::Undo, shortcut(Modifiers::CTRL, Key::U)).unwrap();
+        shortcuts.reset(Action::Undo).unwrap();
+        assert_eq!(shortcuts, AllKeyboardShortcuts::default());
+
+        shortcuts.replace(Action::Undo, shortcut(Modifiers::CTRL, Key::U)).unwrap();
+        shortcuts.replace(Action::Redo, shortcut(Modifiers::CTRL, Key::Z)).unwrap();
+        assert_eq!(in_use_by(shortcuts.reset(Action::Undo)), Action::Redo.label());
+        assert_eq!(shortcuts.shortcuts(Action::Undo), [shortcut(Modifiers::CTRL, Key::U)]);
+    }
+
+    #[test]
+    fn from_map_reads_back_to_map() {
+        let mut shortcuts = AllKeyboardShortcuts::default();
+        shortcuts.replace(Action::Save, shortcut(Modifiers::CTRL | Modifiers::ALT, Key::F2)).unwrap();
+        shortcuts.add(Action::CropTool, shortcut(Modifiers::SHIFT, Key::C)).unwrap();
+        assert_eq!(AllKeyboardShortcuts::from_map(&shortcuts.to_map()), shortcuts);
+    }
+
+    #[test]
+    fn from_map_skips_unknown_actions_and_invalid_shortcuts() {
+        let map: BTreeMap<String, Vec<String>> = [
+            ("save".to_string(), vec!["CTRL+W".to_string(), "CTRL+NOPE".to_string()]),
+            ("launch_rockets".to_string(), vec!["CTRL+R".to_string()]),
+            // Already the default of undo: skipped.
+            ("redo".to_string(), vec!["CTRL+Z".to_string()]),
+        ]
+        .into_iter()
+        .collect();
+        let shortcuts = AllKeyboardShortcuts::from_map(&map);
+        assert_eq!(shortcuts.shortcuts(Action::Save), [shortcut(Modifiers::CTRL, Key::W)]);
+        assert!(shortcuts.shortcuts(Action::Redo).is_empty());
+        assert_eq!(shortcuts.shortcuts(Action::Undo), [shortcut(Modifiers::CTRL, Key::Z)]);
+        assert_eq!(shortcuts.conflict(&shortcut(Modifiers::CTRL, Key::R)), None);
+        assert_eq!(shortcuts.shortcuts(Action::Paste), AllKeyboardShortcuts::default().shortcuts(Action::Paste));
+    }
 }
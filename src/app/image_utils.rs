@@ -1,5 +1,7 @@
-use image::RgbaImage;
+use image::{imageops, DynamicImage, ImageOutputFormat, RgbaImage};
+use std::io::Cursor;
 use egui::ImageData;
+use serde::{Deserialize, Serialize};
 
 
 pub fn load_image_from_memory(image_data: RgbaImage) -> ImageData {
@@ -7,6 +9,69 @@ pub fn load_image_from_memory(image_data: RgbaImage) -> ImageData {
         [image_data.width() as _, image_data.height() as _],
         image_data.as_flat_samples().as_slice(),
     );
-    return ImageData::from(color_image);   
+    ImageData::from(color_image)
+}
+
+/// PNG encoding of `image`.
+pub fn encode_png(image: &RgbaImage) -> Result<Vec<u8>, image::ImageError> {
+    let mut png = Cursor::new(vec![]);
+    DynamicImage::ImageRgba8(image.clone()).write_to(&mut png, ImageOutputFormat::Png)?;
+    Ok(png.into_inner())
+}
+
+pub fn decode_png(png: &[u8]) -> Result<RgbaImage, image::ImageError> {
+    Ok(image::load_from_memory_with_format(png, image::ImageFormat::Png)?.to_rgba8())
+}
+
+/// How a redacted area hides its content.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum RedactionMode {
+    Pixelate,
+    Blur,
+}
+
+/// Pixel area (x, y, width, height) to redact.
+pub type PixelArea = (u32, u32, u32, u32);
+
+/// Overwrites `area` of `image` with a pixelated or blurred copy of itself.
+/// `strength` is the side of the pixel blocks, or the sigma of the Gaussian blur.
+pub fn redact(image: &mut RgbaImage, area: PixelArea, mode: RedactionMode, strength: u32) {
+    let (x, y, width, height) = area;
+    if width == 0 || height == 0 {
+        return;
+    }
+    let region = imageops::crop_imm(image, x, y, width, height).to_image();
+    let redacted = match mode {
+        RedactionMode::Pixelate => pixelate(&region, strength.max(1)),
+        RedactionMode::Blur => imageops::blur(&region, strength.max(1) as f32),
+    };
+    imageops::replace(image, &redacted, x as i64, y as i64);
+}
+
+/// Replaces every `block` x `block` square with its average colour.
+fn pixelate(image: &RgbaImage, block: u32) -> RgbaImage {
+    let mut output = image.clone();
+    for block_y in (0..image.height()).step_by(block as usize) {
+        for block_x in (0..image.width()).step_by(block as usize) {
+            let block_width = block.min(image.width() - block_x);
+            let block_height = block.min(image.height() - block_y);
+            let mut sum = [0u64; 4];
+            for y in block_y..block_y + block_height {
+                for x in block_x..block_x + block_width {
+                    for (channel, value) in sum.iter_mut().zip(image.get_pixel(x, y).0) {
+                        *channel += value as u64;
+                    }
+                }
+            }
+            let count = (block_width * block_height) as u64;
+            let average = image::Rgba(sum.map(|channel| (channel / count) as u8));
+            for y in block_y..block_y + block_height {
+                for x in block_x..block_x + block_width {
+                    output.put_pixel(x, y, average);
+                }
+            }
+        }
+    }
+    output
 }
 
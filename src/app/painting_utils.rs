@@ -3,25 +3,342 @@ use egui::emath;
 use egui::RichText;
 use egui::{Pos2, Rect, Vec2};
 use image::{imageops, RgbaImage};
-use std::{ops::Add, vec};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::vec;
 
 
 use super::crop_utils;
+use super::error_utils::YasaError;
+use super::history_utils::{Command, History};
+use super::image_utils::{self, PixelArea, RedactionMode};
+use super::project_utils::Project;
+use super::hotkeys_utils::{Action, AllKeyboardShortcuts};
+use super::raster_utils::{Primitive, StrokeMask};
+use super::svg_utils;
+use super::text_utils;
 
-#[derive(Clone)]
+/// Largest stroke width offered by the width picker.
+const MAX_STROKE_WIDTH: f32 = 12.0;
+/// Thinnest stroke width offered by the width picker that still draws something.
+const MIN_STROKE_WIDTH: f32 = 1.0;
+/// Space between a text and the border of its background, relative to the font size.
+const TEXT_PADDING: f32 = 0.2;
+/// How far from a shape or a handle, in screen points, a click still selects it.
+const PICK_DISTANCE: f32 = 6.0;
+/// Zoom limits, in screen pixels per pixel of the screenshot.
+const MIN_ZOOM: f32 = 0.05;
+const MAX_ZOOM: f32 = 32.0;
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 
 pub struct DrawObj {
     points: Vec<Pos2>,
     stroke: egui::Stroke,
+    /// Lines use every point, rectangles and ellipses the two opposite corners of their bounding box.
+    shape: DrawingShape,
+    filled: bool,
+    /// Content of a `DrawingShape::Text`, whose only point is the top-left corner of the text.
+    text: Option<TextBox>,
+    /// How a `DrawingShape::Redaction` hides the pixels of its bounding box.
+    redaction: Option<(RedactionMode, u32)>,
+    /// Picture of a `DrawingShape::Image`, stretched over the bounding box of its two points.
+    #[serde(default)]
+    overlay: Option<Overlay>,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct TextBox {
+    text: String,
+    /// Font size in pixels of the screenshot.
+    size: f32,
+    background: Option<egui::Color32>,
+}
+
+impl TextBox {
+    /// Area covered by the text and its background, in pixels of the screenshot.
+    fn pixel_rect(&self, position: Pos2) -> Rect {
+        Rect::from_min_size(position, text_utils::text_extent(&self.text, self.size)).expand(self.size * TEXT_PADDING)
+    }
+}
+
+/// An image pasted over the screenshot.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Overlay {
+    /// Identifies the texture showing the image: copies of the overlay share it.
+    /// In a project, it also names the PNG entry holding the image, see `project_utils`.
+    id: u64,
+    /// Stored apart from the manifest of a project, empty until `project_utils::read_project` loads it.
+    #[serde(skip)]
+    image: Arc<RgbaImage>,
+}
+
+// The image never changes once pasted, comparing the ids is enough.
+impl PartialEq for Overlay {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+fn next_overlay_id() -> u64 {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+impl Overlay {
+    pub fn new(image: RgbaImage) -> Self {
+        Self { id: next_overlay_id(), image: Arc::new(image) }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn image(&self) -> &RgbaImage {
+        &self.image
+    }
+
+    fn size(&self) -> Vec2 {
+        Vec2::new(self.image.width() as f32, self.image.height() as f32)
+    }
+
+    /// Blends the image, scaled to `rect` (in pixels), over `target`.
+    fn draw(&self, target: &mut RgbaImage, rect: Rect) {
+        let rect = Rect::from_min_max(rect.min.round(), rect.max.round());
+        let (width, height) = (rect.width().max(1.0) as u32, rect.height().max(1.0) as u32);
+        let scaled = if (width, height) == self.image.dimensions() {
+            (*self.image).clone()
+        } else {
+            imageops::resize(&*self.image, width, height, imageops::FilterType::Triangle)
+        };
+        imageops::overlay(target, &scaled, rect.min.x as i64, rect.min.y as i64);
+    }
 }
 
 impl DrawObj {
     fn new(points: Vec<Pos2>, stroke: egui::Stroke) -> Self {
         Self {
-            points: points,
-            stroke: stroke,
+            points,
+            stroke,
+            ..Self::default()
         }
     }
+
+    fn bounding_rect(&self) -> Rect {
+        Rect::from_two_pos(self.points[0], self.points[1])
+    }
+
+    /// True for a two-point shape that would not be visible (e.g. a click without dragging).
+    fn is_degenerate(&self) -> bool {
+        match self.shape {
+            DrawingShape::Arrow | DrawingShape::DoubleArrow => self.points[0] == self.points[1],
+            DrawingShape::Redaction => self.bounding_rect().width() <= 0.0 || self.bounding_rect().height() <= 0.0,
+            _ => self.bounding_rect().area() <= 0.0,
+        }
+    }
+
+    /// Screen area of a text annotation.
+    fn text_screen_rect(&self, to_screen: emath::RectTransform) -> Option<Rect> {
+        let text_box = self.text.as_ref()?;
+        Some(to_screen.transform_rect(text_box.pixel_rect(*self.points.first()?)))
+    }
+
+    pub fn kind(&self) -> &DrawingShape {
+        &self.shape
+    }
+
+    /// Checks a shape read from a file, which could not be drawn if it missed points or data of its kind.
+    pub fn validate(&self) -> Result<(), String> {
+        let min_points = match self.shape {
+            DrawingShape::Line | DrawingShape::Highlighter | DrawingShape::Text => 1,
+            DrawingShape::StraightLine
+            | DrawingShape::Rectangle
+            | DrawingShape::Ellipse
+            | DrawingShape::Arrow
+            | DrawingShape::DoubleArrow
+            | DrawingShape::Redaction
+            | DrawingShape::Image => 2,
+            DrawingShape::Select => return Err("a selection is not a shape".to_string()),
+        };
+        let label = self.shape.label();
+        if self.points.len() < min_points {
+            return Err(format!("the {} has {} points, too few to be drawn", label, self.points.len()));
+        }
+        if self.points.iter().any(|p| !p.x.is_finite() || !p.y.is_finite()) {
+            return Err(format!("the {} has a point that is not a finite number", label));
+        }
+        if !self.stroke.width.is_finite() || self.stroke.width < 0.0 {
+            return Err(format!("the {} has an invalid width", label));
+        }
+        let complete = match self.shape {
+            DrawingShape::Text => self.text.as_ref().map_or(false, |text| text.size.is_finite() && text.size > 0.0),
+            DrawingShape::Redaction => self.redaction.is_some(),
+            DrawingShape::Image => self.overlay.is_some(),
+            _ => true,
+        };
+        if !complete {
+            return Err(format!("the {} misses its content", label));
+        }
+        Ok(())
+    }
+
+    /// Picture of a pasted image.
+    pub fn overlay(&self) -> Option<&Overlay> {
+        self.overlay.as_ref()
+    }
+
+    pub fn overlay_mut(&mut self) -> Option<&mut Overlay> {
+        self.overlay.as_mut()
+    }
+
+    /// Box around the shape, in pixels of the screenshot.
+    fn bounds(&self) -> Option<Rect> {
+        let first = *self.points.first()?;
+        if let Some(text_box) = &self.text {
+            return Some(text_box.pixel_rect(first));
+        }
+        Some(self.points.iter().fold(Rect::from_min_max(first, first), |rect, p| rect.union(Rect::from_min_max(*p, *p))))
+    }
+
+    /// True if `point` (in pixels of the screenshot) is on the shape, or within `tolerance` pixels of it.
+    fn hit(&self, point: Pos2, tolerance: f32) -> bool {
+        match self.shape {
+            DrawingShape::Text | DrawingShape::Redaction | DrawingShape::Image => {
+                self.bounds().map_or(false, |bounds| bounds.expand(tolerance).contains(point))
+            }
+            _ => self.primitives().iter().any(|primitive| primitive.distance(point) <= tolerance),
+        }
+    }
+
+    /// Copy of the shape with every point moved by `transform`, and texts scaled by `text_scale`.
+    fn transformed(&self, transform: impl Fn(Pos2) -> Pos2, text_scale: f32) -> Self {
+        let mut shape = self.clone();
+        shape.points = self.points.iter().map(|p| transform(*p)).collect();
+        if let Some(text_box) = shape.text.as_mut() {
+            text_box.size = (text_box.size * text_scale).max(6.0);
+        }
+        shape
+    }
+
+    /// Geometry of the shape in pixels of the screenshot. Texts, redactions and images have none.
+    fn primitives(&self) -> Vec<Primitive> {
+        if self.points.len() < 2 {
+            return vec![];
+        }
+        let width = self.stroke.width;
+        let closed = |mut points: Vec<Pos2>| {
+            points.push(points[0]);
+            points
+        };
+        match self.shape {
+            DrawingShape::Line | DrawingShape::StraightLine | DrawingShape::Highlighter => {
+                vec![Primitive::Stroke(self.points.clone(), width)]
+            }
+            DrawingShape::Rectangle => {
+                let rect = self.bounding_rect();
+                let corners = vec![rect.left_top(), rect.right_top(), rect.right_bottom(), rect.left_bottom()];
+                if self.filled {
+                    vec![Primitive::Fill(corners)]
+                } else {
+                    vec![Primitive::Stroke(closed(corners), width)]
+                }
+            }
+            DrawingShape::Ellipse => {
+                let points = ellipse_points(self.bounding_rect());
+                if self.filled {
+                    vec![Primitive::Fill(points)]
+                } else {
+                    vec![Primitive::Stroke(closed(points), width)]
+                }
+            }
+            DrawingShape::Arrow | DrawingShape::DoubleArrow => {
+                arrow_primitives(self.points[0], self.points[1], width, self.shape == DrawingShape::DoubleArrow)
+            }
+            DrawingShape::Text | DrawingShape::Redaction | DrawingShape::Image | DrawingShape::Select => vec![],
+        }
+    }
+
+    /// The shape as drawn on the canvas, `to_screen` mapping pixels of the screenshot to the screen.
+    /// `None` while it has not enough points; redactions are applied to the texture itself, see `Painting::update_redactions`,
+    /// and images need their own texture, see `Painting::overlay_textures`.
+    fn screen_shape(&self, painter: &egui::Painter, to_screen: emath::RectTransform) -> Option<egui::Shape> {
+        let points_per_pixel = to_screen.scale().x;
+        if self.shape == DrawingShape::Text {
+            let text_box = self.text.as_ref()?;
+            let position = to_screen * *self.points.first()?;
+            let font = egui::FontId::new(text_box.size * points_per_pixel, text_utils::font_family());
+            let galley = painter.layout_no_wrap(text_box.text.clone(), font, self.stroke.color);
+            let mut shapes = vec![];
+            if let Some(background) = text_box.background {
+                let rect = self.text_screen_rect(to_screen)?;
+                shapes.push(egui::Shape::rect_filled(rect, 0.0, background));
+            }
+            shapes.push(egui::Shape::galley(position, galley));
+            return Some(egui::Shape::Vec(shapes));
+        }
+
+        let shapes: Vec<egui::Shape> = self
+            .primitives()
+            .into_iter()
+            .map(|primitive| match primitive {
+                Primitive::Stroke(points, width) => {
+                    let mut points: Vec<Pos2> = points.iter().map(|p| to_screen * *p).collect();
+                    let stroke = egui::Stroke::new(width * points_per_pixel, self.stroke.color);
+                    if points.len() > 2 && points.first() == points.last() {
+                        points.pop();
+                        egui::Shape::closed_line(points, stroke)
+                    } else {
+                        egui::Shape::line(points, stroke)
+                    }
+                }
+                Primitive::Fill(polygon) => egui::Shape::convex_polygon(
+                    polygon.iter().map(|p| to_screen * *p).collect(),
+                    self.stroke.color,
+                    egui::Stroke::NONE,
+                ),
+            })
+            .collect();
+        (!shapes.is_empty()).then_some(egui::Shape::Vec(shapes))
+    }
+
+    /// The shape as SVG, in pixels of the screenshot. Redactions have none: they are applied to the embedded image.
+    fn svg_element(&self) -> Result<Option<String>, YasaError> {
+        let color = self.stroke.color;
+        let width = self.stroke.width;
+        if self.shape == DrawingShape::Text {
+            let (Some(text_box), Some(position)) = (&self.text, self.points.first()) else {
+                return Ok(None);
+            };
+            let mut elements = vec![];
+            if let Some(background) = text_box.background {
+                elements.push(svg_utils::rect(text_box.pixel_rect(*position), &svg_utils::shape_style(background, true, 0.0)));
+            }
+            let baseline = *position + Vec2::new(0.0, text_utils::ascent(text_box.size));
+            let line_height = text_utils::line_height(text_box.size);
+            let family = text_utils::ANNOTATION_FONT_SVG_FAMILY;
+            elements.push(svg_utils::text(&text_box.text, baseline, text_box.size, line_height, family, color));
+            return Ok(Some(svg_utils::group(&elements)));
+        }
+        if self.points.len() < 2 {
+            return Ok(None);
+        }
+        let element = match self.shape {
+            DrawingShape::Line | DrawingShape::StraightLine | DrawingShape::Highlighter => {
+                svg_utils::polyline(&self.points, width, color)
+            }
+            DrawingShape::Rectangle => svg_utils::rect(self.bounding_rect(), &svg_utils::shape_style(color, self.filled, width)),
+            DrawingShape::Ellipse => svg_utils::ellipse(self.bounding_rect(), &svg_utils::shape_style(color, self.filled, width)),
+            DrawingShape::Arrow | DrawingShape::DoubleArrow => svg_utils::primitives(&self.primitives(), color),
+            DrawingShape::Image => match &self.overlay {
+                Some(overlay) => svg_utils::image(self.bounding_rect(), &image_utils::encode_png(&overlay.image)?),
+                None => return Ok(None),
+            },
+            DrawingShape::Text | DrawingShape::Redaction | DrawingShape::Select => return Ok(None),
+        };
+        Ok(Some(element))
+    }
 }
 
 impl Default for DrawObj {
@@ -32,6 +349,11 @@ impl Default for DrawObj {
                 1.0,
                 egui::Color32::from_rgba_unmultiplied(18, 160, 215, 255),
             ),
+            shape: DrawingShape::Line,
+            filled: false,
+            text: None,
+            redaction: None,
+            overlay: None,
         }
     }
 }
@@ -41,25 +363,96 @@ pub struct Painting {
     pub texture: Option<egui::TextureHandle>,
     pub shapes: Vec<DrawObj>,
     stroke: egui::Stroke,
-    aspect_ratio: f32,
     pub screenshot_image_buffer: Option<RgbaImage>,
-    last_actions: Vec<DrawObj>,
+    history: History,
+    /// Index and previous state of the shape being moved or restyled, recorded once the mouse is released.
+    pending_edit: Option<(usize, DrawObj)>,
     pub ui_size: egui::Rect,
     pub ui_position: egui::Pos2,
     selected_shape: DrawingShape,
+    fill_shape: bool,
+    /// Width and translucent colour of the highlighter, kept apart from the pen.
+    highlighter: egui::Stroke,
+    text_size: f32,
+    text_background_enabled: bool,
+    text_background: egui::Color32,
+    /// Index in `shapes` of the text annotation being written.
+    editing_text: Option<usize>,
+    focus_text_editor: bool,
+    /// The text annotation being edited as it was before, `None` for a new one.
+    text_before: Option<DrawObj>,
+    /// Index in `shapes` of the shape picked with the selection tool.
+    selection: Option<usize>,
+    selection_drag: Option<SelectionDrag>,
+    redaction_mode: RedactionMode,
+    redaction_strength: u32,
+    /// Redactions currently applied to `texture`, `None` when it must be uploaded again.
+    previewed_redactions: Option<Vec<(PixelArea, RedactionMode, u32)>>,
+    /// Maps pixels of the screenshot to the screen.
     to_screen: egui::emath::RectTransform,
+    /// Screen pixels per pixel of the screenshot, `None` to fit the canvas in the window.
+    zoom: Option<f32>,
+    /// Offset of the centre of the zoomed screenshot from the centre of the canvas, in points.
+    pan: Vec2,
+    /// Zoom of the last frame, fitted or not, as shown in the toolbar.
+    shown_zoom: f32,
     crop: Option<crop_utils::Crop>,
+    /// Part of the screenshot that is kept, in pixels, `None` for all of it.
+    /// The image itself is never cut: the crop is applied when it is shown or exported.
+    crop_area: Option<PixelArea>,
+    /// Set when the crop changed the size of the output, see `take_resize`.
+    resized: bool,
+    /// Textures of the pasted images, by overlay id.
+    overlay_textures: HashMap<u64, egui::TextureHandle>,
     pub active_shape: bool,
-    original_size: (u32, u32),
     ruler_button_flag: bool,
     color_button_flag: bool,
     shape_button_flag: bool,
 }
 
-#[derive(Clone, Debug, PartialEq)]
-enum DrawingShape {
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum DrawingShape {
     Line,
     StraightLine,
+    Rectangle,
+    Ellipse,
+    Arrow,
+    DoubleArrow,
+    Text,
+    Redaction,
+    Highlighter,
+    /// An image pasted from the clipboard. Not a tool: see `Painting::paste_image`.
+    Image,
+    /// Not a shape: picks, moves and resizes the existing ones.
+    Select,
+}
+
+impl DrawingShape {
+    /// Name of the tool, as shown in the history.
+    pub fn label(&self) -> &'static str {
+        match self {
+            DrawingShape::Line => "line",
+            DrawingShape::StraightLine => "straight line",
+            DrawingShape::Rectangle => "rectangle",
+            DrawingShape::Ellipse => "ellipse",
+            DrawingShape::Arrow => "arrow",
+            DrawingShape::DoubleArrow => "double arrow",
+            DrawingShape::Text => "text",
+            DrawingShape::Redaction => "redaction",
+            DrawingShape::Highlighter => "highlight",
+            DrawingShape::Image => "image",
+            DrawingShape::Select => "selection",
+        }
+    }
+}
+
+/// What the mouse is doing with the selected shape.
+#[derive(Clone)]
+enum SelectionDrag {
+    /// Last pointer position, in pixels of the screenshot.
+    Move(Pos2),
+    /// The shape as it was before the drag, and the corner of its bounds that stays in place.
+    Resize { original: DrawObj, anchor: Pos2, corner: Pos2 },
 }
 
 impl Default for Painting {
@@ -72,15 +465,33 @@ impl Default for Painting {
             ),
             texture: None,
             screenshot_image_buffer: None,
-            aspect_ratio: 1.,
-            last_actions: vec![],
+            history: History::default(),
+            pending_edit: None,
             ui_size: egui::Rect::from_min_size(egui::Pos2::ZERO, egui::Vec2::ZERO),
             ui_position: egui::Pos2::ZERO,
             selected_shape: DrawingShape::Line,
+            fill_shape: false,
+            highlighter: egui::Stroke::new(16.0, egui::Color32::from_rgba_unmultiplied(255, 230, 0, 96)),
+            text_size: 32.0,
+            text_background_enabled: false,
+            text_background: egui::Color32::WHITE,
+            editing_text: None,
+            focus_text_editor: false,
+            text_before: None,
+            selection: None,
+            selection_drag: None,
+            redaction_mode: RedactionMode::Pixelate,
+            redaction_strength: 12,
+            previewed_redactions: None,
             crop: None,
+            crop_area: None,
+            resized: false,
+            overlay_textures: HashMap::new(),
             to_screen: emath::RectTransform::identity(Rect::NOTHING),
+            zoom: None,
+            pan: Vec2::ZERO,
+            shown_zoom: 1.0,
             active_shape: false,
-            original_size: (0, 0),
             ruler_button_flag: false,
             color_button_flag: false,
             shape_button_flag: false,
@@ -95,25 +506,531 @@ impl Painting {
     ) -> Self {
         Self {
             texture: texture.clone(),
-            aspect_ratio: texture.unwrap().aspect_ratio(),
             screenshot_image_buffer: screenshot_image_buffer.clone(),
-            original_size: (screenshot_image_buffer.clone().unwrap().width(), screenshot_image_buffer.clone().unwrap().height()),
             ..Self::default()
         }
     }
 
 
-    pub fn new_crop(
-        texture: Option<egui::TextureHandle>,
-        screenshot_image_buffer: Option<RgbaImage>,
-        shapes: Vec<DrawObj>,
-    ) -> Self {
-        Self {
-            texture: texture.clone(),
-            aspect_ratio: texture.unwrap().aspect_ratio(),
-            screenshot_image_buffer,
-            shapes,
-            ..Self::default()
+    /// Editor for a project saved earlier, `texture` showing its image.
+    pub fn from_project(texture: egui::TextureHandle, project: Project) -> Self {
+        let mut painting = Self::new(Some(texture), Some(project.image));
+        painting.shapes = project.shapes;
+        painting.shapes.push(DrawObj::new(vec![], painting.stroke));
+        painting.crop_area = project.crop;
+        painting
+    }
+
+    /// The screenshot, its annotations and its crop, to be saved as a project.
+    /// With `bake_redactions`, the pixels under the redactions are replaced in the image too: the redactions can still
+    /// be moved once the project is opened again, but what they hid is gone.
+    pub fn project(&self, bake_redactions: bool) -> Project {
+        Project {
+            image: if bake_redactions { self.redacted_image() } else { self.screenshot_image_buffer.clone().unwrap() },
+            shapes: self.shapes[..self.shapes.len().saturating_sub(1)].to_vec(),
+            crop: self.crop_area,
+        }
+    }
+
+    pub fn stroke(&self) -> egui::Stroke {
+        self.stroke
+    }
+
+    pub fn selected_shape(&self) -> DrawingShape {
+        self.selected_shape.clone()
+    }
+
+    /// Restores the tool choice of a previous session.
+    pub fn set_tools(&mut self, stroke: egui::Stroke, shape: DrawingShape) {
+        self.stroke = stroke;
+        self.selected_shape = shape;
+    }
+
+    /// Runs the editing actions whose shortcut was pressed this frame.
+    pub fn handle_shortcuts(&mut self, ctx: &egui::Context, shortcuts: &AllKeyboardShortcuts) {
+        if self.crop.is_some() {
+            if shortcuts.consume(ctx, Action::LeaveCrop) {
+                self.cancel_crop();
+            } else if let Some(crop) = self.crop.as_mut() {
+                crop.nudge(ctx);
+            }
+            return;
+        }
+        if shortcuts.consume(ctx, Action::PenTool) {
+            self.select_tool(DrawingShape::Line);
+        }
+        if shortcuts.consume(ctx, Action::StraightLineTool) {
+            self.select_tool(DrawingShape::StraightLine);
+        }
+        if shortcuts.consume(ctx, Action::RectangleTool) {
+            self.select_tool(DrawingShape::Rectangle);
+        }
+        if shortcuts.consume(ctx, Action::EllipseTool) {
+            self.select_tool(DrawingShape::Ellipse);
+        }
+        if shortcuts.consume(ctx, Action::ArrowTool) {
+            self.select_tool(DrawingShape::Arrow);
+        }
+        if shortcuts.consume(ctx, Action::TextTool) {
+            self.select_tool(DrawingShape::Text);
+        }
+        if shortcuts.consume(ctx, Action::RedactionTool) {
+            self.select_tool(DrawingShape::Redaction);
+        }
+        if shortcuts.consume(ctx, Action::HighlighterTool) {
+            self.select_tool(DrawingShape::Highlighter);
+        }
+        if shortcuts.consume(ctx, Action::SelectTool) {
+            self.select_tool(DrawingShape::Select);
+        }
+        if shortcuts.consume(ctx, Action::DeleteSelected) {
+            self.delete_selection();
+        }
+        if shortcuts.consume(ctx, Action::CropTool) {
+            self.start_crop();
+        }
+        if shortcuts.consume(ctx, Action::Undo) && self.can_undo() {
+            self.undo();
+        }
+        if shortcuts.consume(ctx, Action::Redo) && self.can_redo() {
+            self.redo();
+        }
+        if shortcuts.consume(ctx, Action::StrokeWidthUp) {
+            self.stroke.width = (self.stroke.width + 1.0).min(MAX_STROKE_WIDTH);
+        }
+        if shortcuts.consume(ctx, Action::StrokeWidthDown) {
+            // A width of 0 draws nothing: it can be picked on purpose, not reached by the shortcut.
+            if self.stroke.width > MIN_STROKE_WIDTH {
+                self.stroke.width = (self.stroke.width - 1.0).max(MIN_STROKE_WIDTH);
+            }
+        }
+    }
+
+    fn select_tool(&mut self, shape: DrawingShape) {
+        self.finish_pending_edits();
+        self.clear_selection();
+        self.selected_shape = shape;
+        self.active_shape = true;
+    }
+
+    fn clear_selection(&mut self) {
+        self.selection = None;
+        self.selection_drag = None;
+    }
+
+    fn delete_selection(&mut self) {
+        self.finish_pending_edits();
+        if let Some(index) = self.selection.take() {
+            self.selection_drag = None;
+            self.execute(Command::Remove { index, shape: self.shapes[index].clone() });
+        }
+    }
+
+    /// Topmost shape under `pointer_pos` (screen coordinates), ignoring the one being drawn.
+    fn shape_at(&self, pointer_pos: Pos2) -> Option<usize> {
+        let pixels_per_point = 1.0 / self.to_screen.scale().x;
+        let point = self.to_screen.inverse() * pointer_pos;
+        let completed = self.shapes.len().saturating_sub(1);
+        (0..completed)
+            .rev()
+            .find(|index| self.shapes[*index].hit(point, PICK_DISTANCE * pixels_per_point))
+    }
+
+    /// Screen rectangles of the resize handles of the selected shape, with the pixel position of their corner.
+    fn selection_handles(&self) -> Vec<(Rect, Pos2)> {
+        let Some(bounds) = self.selection.and_then(|index| self.shapes[index].bounds()) else {
+            return vec![];
+        };
+        [bounds.left_top(), bounds.right_top(), bounds.right_bottom(), bounds.left_bottom()]
+            .into_iter()
+            .map(|corner| (Rect::from_center_size(self.to_screen * corner, Vec2::splat(PICK_DISTANCE * 1.5)), corner))
+            .collect()
+    }
+
+    /// Selection tool: click to pick a shape, drag it to move it or drag a handle to resize it.
+    fn select_ui(&mut self, response: &egui::Response) {
+        let from_screen = self.to_screen.inverse();
+        if response.drag_started() {
+            if let Some(pointer_pos) = response.interact_pointer_pos() {
+                let handle = self
+                    .selection_handles()
+                    .into_iter()
+                    .find(|(rect, _)| rect.expand(PICK_DISTANCE / 2.0).contains(pointer_pos));
+                self.commit_pending_edit();
+                if let (Some((_, corner)), Some(index)) = (handle, self.selection) {
+                    let bounds = self.shapes[index].bounds().unwrap();
+                    let anchor = bounds.center() + (bounds.center() - corner);
+                    self.selection_drag = Some(SelectionDrag::Resize { original: self.shapes[index].clone(), anchor, corner });
+                } else {
+                    self.selection = self.shape_at(pointer_pos);
+                    self.selection_drag = self.selection.map(|_| SelectionDrag::Move(from_screen * pointer_pos));
+                }
+                self.pending_edit = self.selection.map(|index| (index, self.shapes[index].clone()));
+            }
+        }
+
+        if let (Some(index), Some(drag), Some(pointer_pos)) =
+            (self.selection, self.selection_drag.clone(), response.interact_pointer_pos())
+        {
+            let pixel_pos = from_screen * pointer_pos;
+            match drag {
+                SelectionDrag::Move(last) => {
+                    let delta = pixel_pos - last;
+                    self.shapes[index] = self.shapes[index].transformed(|p| p + delta, 1.0);
+                    self.selection_drag = Some(SelectionDrag::Move(pixel_pos));
+                }
+                SelectionDrag::Resize { original, anchor, corner } => {
+                    let scale_along = |new: f32, old: f32| if old.abs() > f32::EPSILON { new / old } else { 1.0 };
+                    let scale = Vec2::new(
+                        scale_along(pixel_pos.x - anchor.x, corner.x - anchor.x),
+                        scale_along(pixel_pos.y - anchor.y, corner.y - anchor.y),
+                    );
+                    self.shapes[index] = original.transformed(|p| anchor + (p - anchor) * scale, scale.y.abs());
+                }
+            }
+        }
+
+        if response.drag_released() {
+            self.selection_drag = None;
+        }
+    }
+
+    /// Colour, width and the other properties of the selected shape.
+    fn selection_properties_ui(&mut self, ui: &mut egui::Ui) {
+        let Some(index) = self.selection else {
+            return;
+        };
+        let before = self.shapes[index].clone();
+        let shape = &mut self.shapes[index];
+        match shape.shape {
+            DrawingShape::Redaction => {
+                if let Some((mode, strength)) = shape.redaction.as_mut() {
+                    ui.selectable_value(mode, RedactionMode::Pixelate, "Pixelate");
+                    ui.selectable_value(mode, RedactionMode::Blur, "Blur");
+                    ui.add(egui::Slider::new(strength, 2..=40));
+                }
+            }
+            DrawingShape::Image => {
+                if let Some(size) = shape.overlay.as_ref().map(Overlay::size) {
+                    if ui.button(RichText::new("1:1").size(30.0)).on_hover_text("Original size").clicked() {
+                        let min = shape.bounding_rect().min;
+                        shape.points = vec![min, min + size];
+                    }
+                }
+            }
+            DrawingShape::Text => {
+                ui.color_edit_button_srgba(&mut shape.stroke.color);
+                if let Some(text_box) = shape.text.as_mut() {
+                    ui.add(egui::DragValue::new(&mut text_box.size).clamp_range(6.0..=400.0).suffix(" px"));
+                }
+            }
+            _ => {
+                ui.color_edit_button_srgba(&mut shape.stroke.color);
+                if !shape.filled {
+                    ui.add(egui::Slider::new(&mut shape.stroke.width, 0.0..=48.0)).on_hover_text("Width");
+                }
+                if matches!(shape.shape, DrawingShape::Rectangle | DrawingShape::Ellipse) {
+                    ui.checkbox(&mut shape.filled, "Fill");
+                }
+            }
+        }
+        if self.pending_edit.is_none() && self.shapes[index] != before {
+            self.pending_edit = Some((index, before));
+        }
+        if ui.button(RichText::new("🗑").size(30.0)).on_hover_text("Delete").clicked() {
+            self.delete_selection();
+        }
+    }
+
+    /// Stops editing the current text annotation and records it, dropping it if it was left empty.
+    fn finish_text_editing(&mut self) {
+        let Some(index) = self.editing_text.take() else {
+            return;
+        };
+        let before = self.text_before.take();
+        let empty = self.shapes[index].text.as_ref().map_or(true, |text_box| text_box.text.trim().is_empty());
+        if empty {
+            self.shapes.remove(index);
+            if let Some(shape) = before {
+                self.history.record(Command::Remove { index, shape });
+            }
+            return;
+        }
+        let after = self.shapes[index].clone();
+        match before {
+            None => self.history.record(Command::Add { index, shape: after }),
+            Some(before) if before != after => self.history.record(Command::Edit { index, before, after }),
+            Some(_) => {}
+        }
+    }
+
+    /// Records the changes to the selected shape, once they are over.
+    fn commit_pending_edit(&mut self) {
+        if let Some((index, before)) = self.pending_edit.take() {
+            let after = self.shapes[index].clone();
+            if before != after {
+                self.history.record(Command::Edit { index, before, after });
+            }
+        }
+    }
+
+    /// Completes the shape being drawn, if any, and records it.
+    fn commit_current_shape(&mut self) {
+        let Some(current) = self.shapes.last_mut() else {
+            return;
+        };
+        if current.shape == DrawingShape::StraightLine && current.points.len() < 2 {
+            current.points.clear();
+        }
+        if current.points.is_empty() {
+            return;
+        }
+        let index = self.shapes.len() - 1;
+        self.history.record(Command::Add { index, shape: self.shapes[index].clone() });
+        self.shapes.push(DrawObj::new(vec![], self.stroke));
+    }
+
+    /// Adds `image` over the screenshot, centred on its visible part and shrunk to fit in it,
+    /// and selects it so that it can be moved and resized right away.
+    pub fn paste_image(&mut self, image: RgbaImage) {
+        self.cancel_crop();
+        self.finish_pending_edits();
+        if self.shapes.is_empty() {
+            self.shapes.push(DrawObj::new(vec![], self.stroke));
+        }
+        let (x, y, width, height) = self.visible_area();
+        let area = Rect::from_min_size(Pos2::new(x as f32, y as f32), Vec2::new(width as f32, height as f32));
+        let overlay = Overlay::new(image);
+        let size = overlay.size();
+        let scale = (area.width() * 0.8 / size.x).min(area.height() * 0.8 / size.y).min(1.0);
+        let rect = Rect::from_center_size(area.center(), size * scale);
+        let mut shape = DrawObj::new(vec![rect.min, rect.max], self.stroke);
+        shape.shape = DrawingShape::Image;
+        shape.overlay = Some(overlay);
+        let index = self.shapes.len() - 1;
+        self.execute(Command::Add { index, shape });
+        self.select_tool(DrawingShape::Select);
+        self.selection = Some(index);
+    }
+
+    /// Records everything still in progress, so that it becomes part of the history.
+    fn finish_pending_edits(&mut self) {
+        self.finish_text_editing();
+        self.commit_pending_edit();
+        self.commit_current_shape();
+    }
+
+    /// Applies `command` and adds it to the history.
+    fn execute(&mut self, command: Command) {
+        command.apply(&mut self.shapes, &mut self.crop_area);
+        self.resized |= command.changes_crop();
+        self.history.record(command);
+    }
+
+    /// Size in pixels of the image that is saved or copied, once any crop is applied.
+    pub fn output_size(&self) -> (u32, u32) {
+        let (_, _, width, height) = self.visible_area();
+        (width, height)
+    }
+
+    /// The new output size if a crop (or undoing it) changed it since the last call.
+    pub fn take_resize(&mut self) -> Option<(u32, u32)> {
+        std::mem::take(&mut self.resized).then(|| self.output_size())
+    }
+
+    /// Part of the screenshot that ends up in the output, in pixels.
+    fn visible_area(&self) -> PixelArea {
+        let image = self.screenshot_image_buffer.as_ref().unwrap();
+        self.crop_area.unwrap_or((0, 0, image.width(), image.height()))
+    }
+
+    /// Part of the screenshot shown on the canvas: all of it while cropping, so that the crop can be widened again.
+    fn view_rect(&self) -> Rect {
+        let (x, y, width, height) = if self.crop.is_some() {
+            let image = self.screenshot_image_buffer.as_ref().unwrap();
+            (0, 0, image.width(), image.height())
+        } else {
+            self.visible_area()
+        };
+        Rect::from_min_size(Pos2::new(x as f32, y as f32), Vec2::new(width as f32, height as f32))
+    }
+
+    /// Places a new text annotation at `position` (canvas coordinates), or edits the one already there.
+    fn edit_text_at(&mut self, pointer_pos: Pos2, position: Pos2) {
+        self.finish_text_editing();
+        let to_screen = self.to_screen;
+        let existing = self.shapes.iter().rposition(|shape| {
+            shape
+                .text_screen_rect(to_screen)
+                .map_or(false, |rect| rect.contains(pointer_pos))
+        });
+        let index = match existing {
+            Some(index) => {
+                self.text_before = Some(self.shapes[index].clone());
+                let shape = &self.shapes[index];
+                let text_box = shape.text.as_ref().unwrap();
+                self.stroke.color = shape.stroke.color;
+                self.text_size = text_box.size;
+                self.text_background_enabled = text_box.background.is_some();
+                if let Some(background) = text_box.background {
+                    self.text_background = background;
+                }
+                index
+            }
+            None => {
+                let mut text = DrawObj::new(vec![position], self.stroke);
+                text.shape = DrawingShape::Text;
+                text.text = Some(TextBox {
+                    text: String::new(),
+                    size: self.text_size,
+                    background: None,
+                });
+                // The last shape is the one being drawn, keep it last.
+                let index = self.shapes.len().saturating_sub(1);
+                self.shapes.insert(index, text);
+                index
+            }
+        };
+        self.editing_text = Some(index);
+        self.focus_text_editor = true;
+    }
+
+    /// Shows the text field of the annotation being edited, just below it on the canvas.
+    fn text_editor_ui(&mut self, ctx: &egui::Context) {
+        let Some(index) = self.editing_text else {
+            return;
+        };
+        let background = self.text_background_enabled.then_some(self.text_background);
+        let shape = &mut self.shapes[index];
+        shape.stroke.color = self.stroke.color;
+        let Some(text_box) = shape.text.as_mut() else {
+            return;
+        };
+        text_box.size = self.text_size;
+        text_box.background = background;
+
+        let Some(rect) = shape.text_screen_rect(self.to_screen) else {
+            return;
+        };
+        let mut done = false;
+        egui::Area::new("text_annotation_editor")
+            .fixed_pos(rect.left_bottom() + Vec2::new(0.0, 4.0))
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    let text_box = shape.text.as_mut().unwrap();
+                    let response = ui.add(
+                        egui::TextEdit::multiline(&mut text_box.text)
+                            .desired_rows(1)
+                            .desired_width(200.0)
+                            .hint_text("Text"),
+                    );
+                    if self.focus_text_editor {
+                        response.request_focus();
+                        self.focus_text_editor = false;
+                    }
+                    done = ui.button("✔").on_hover_text("Done").clicked();
+                });
+            });
+        if done {
+            self.finish_text_editing();
+        }
+    }
+
+    fn start_crop(&mut self) {
+        self.finish_pending_edits();
+        self.clear_selection();
+        self.active_shape = false;
+        let image = self.screenshot_image_buffer.as_ref().unwrap();
+        let image_size = Vec2::new(image.width() as f32, image.height() as f32);
+        let (x, y, width, height) = self.visible_area();
+        let area = Rect::from_min_size(Pos2::new(x as f32, y as f32), Vec2::new(width as f32, height as f32));
+        self.crop = Some(crop_utils::Crop::from_area(area, image_size));
+    }
+
+    fn cancel_crop(&mut self) {
+        self.active_shape = true;
+        self.crop = None;
+    }
+
+    /// Keeps the rectangle chosen with `crop`. Annotations stay where they are, outside of it they are just hidden.
+    fn apply_crop(&mut self) {
+        let Some(crop) = self.crop.take() else {
+            return;
+        };
+        let Some((image_width, image_height)) = self.screenshot_image_buffer.as_ref().map(RgbaImage::dimensions) else {
+            return;
+        };
+        if image_width == 0 || image_height == 0 {
+            return;
+        }
+        self.active_shape = true;
+        let cut_rect = crop.area();
+        let x = (cut_rect.min.x.max(0.0) as u32).min(image_width - 1);
+        let y = (cut_rect.min.y.max(0.0) as u32).min(image_height - 1);
+        let width = (cut_rect.width() as u32).clamp(1, image_width - x);
+        let height = (cut_rect.height() as u32).clamp(1, image_height - y);
+        let area = Some((x, y, width, height)).filter(|area| *area != (0, 0, image_width, image_height));
+        if area != self.crop_area {
+            self.execute(Command::Crop { before: self.crop_area, after: area });
+        }
+    }
+
+    // The last element of `shapes` is the shape being drawn, it can be undone as well.
+    fn can_undo(&self) -> bool {
+        self.history.can_undo() || self.shapes.last().map_or(false, |shape| !shape.points.is_empty())
+    }
+
+    fn can_redo(&self) -> bool {
+        self.history.can_redo()
+    }
+
+    fn undo(&mut self) {
+        self.finish_pending_edits();
+        self.clear_selection();
+        if let Some(command) = self.history.undo() {
+            command.revert(&mut self.shapes, &mut self.crop_area);
+            self.resized |= command.changes_crop();
+        }
+    }
+
+    fn redo(&mut self) {
+        self.finish_pending_edits();
+        self.clear_selection();
+        if let Some(command) = self.history.redo() {
+            command.apply(&mut self.shapes, &mut self.crop_area);
+            self.resized |= command.changes_crop();
+        }
+    }
+
+    /// Undoes or redoes commands until the first `position` ones are applied.
+    fn jump_to_history(&mut self, position: usize) {
+        self.finish_pending_edits();
+        while self.history.position() > position && self.history.can_undo() {
+            self.undo();
+        }
+        while self.history.position() < position && self.history.can_redo() {
+            self.redo();
+        }
+    }
+
+    /// Every edit made so far, the undone ones greyed out: clicking one goes back (or forward) to it.
+    fn history_ui(&mut self, ui: &mut egui::Ui) {
+        let position = self.history.position();
+        let mut target = None;
+        egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+            if ui.selectable_label(position == 0, "Screenshot").clicked() {
+                target = Some(0);
+            }
+            for (i, command) in self.history.commands().enumerate() {
+                let label = if i < position { RichText::new(command.label()) } else { RichText::new(command.label()).weak() };
+                if ui.selectable_label(i + 1 == position, label).clicked() {
+                    target = Some(i + 1);
+                }
+            }
+        });
+        if let Some(target) = target {
+            self.jump_to_history(target);
+            ui.close_menu();
         }
     }
 
@@ -231,7 +1148,70 @@ impl Painting {
                                 DrawingShape::StraightLine,
                                 "Straight line",
                             );
+                            ui.selectable_value(
+                                &mut self.selected_shape,
+                                DrawingShape::Rectangle,
+                                "Rectangle",
+                            );
+                            ui.selectable_value(
+                                &mut self.selected_shape,
+                                DrawingShape::Ellipse,
+                                "Ellipse",
+                            );
+                            ui.selectable_value(
+                                &mut self.selected_shape,
+                                DrawingShape::Arrow,
+                                "Arrow",
+                            );
+                            ui.selectable_value(
+                                &mut self.selected_shape,
+                                DrawingShape::DoubleArrow,
+                                "Double arrow",
+                            );
+                            ui.selectable_value(
+                                &mut self.selected_shape,
+                                DrawingShape::Text,
+                                "Text",
+                            );
+                            ui.selectable_value(
+                                &mut self.selected_shape,
+                                DrawingShape::Redaction,
+                                "Redact",
+                            );
+                            ui.selectable_value(
+                                &mut self.selected_shape,
+                                DrawingShape::Highlighter,
+                                "Highlighter",
+                            );
+                            ui.selectable_value(
+                                &mut self.selected_shape,
+                                DrawingShape::Select,
+                                "Select",
+                            );
                         });
+                        if self.selected_shape == DrawingShape::Highlighter {
+                            ui.add(egui::Slider::new(&mut self.highlighter.width, 4.0..=48.0))
+                                .on_hover_text("Highlighter width");
+                            ui.color_edit_button_srgba(&mut self.highlighter.color);
+                        }
+                        if self.selected_shape == DrawingShape::Redaction {
+                            ui.selectable_value(&mut self.redaction_mode, RedactionMode::Pixelate, "Pixelate");
+                            ui.selectable_value(&mut self.redaction_mode, RedactionMode::Blur, "Blur");
+                            ui.add(egui::Slider::new(&mut self.redaction_strength, 2..=40))
+                                .on_hover_text("Block size or blur radius, in pixels of the screenshot");
+                        }
+                        if self.selected_shape == DrawingShape::Text {
+                            ui.add(egui::DragValue::new(&mut self.text_size).clamp_range(6.0..=400.0).suffix(" px"))
+                                .on_hover_text("Font size, in pixels of the screenshot");
+                            ui.checkbox(&mut self.text_background_enabled, "Background");
+                            if self.text_background_enabled {
+                                ui.color_edit_button_srgba(&mut self.text_background);
+                            }
+                        }
+                        if matches!(self.selected_shape, DrawingShape::Rectangle | DrawingShape::Ellipse) {
+                            ui.checkbox(&mut self.fill_shape, "Fill")
+                                .on_hover_text("Hold Shift while drawing for a square or a circle");
+                        }
                     }
 
                     if ui.add(egui::Button::new(RichText::new("✏").size(50.0)).fill(if self.active_shape { 
@@ -244,274 +1224,776 @@ impl Painting {
                     }
 
                     if ui.button(RichText::new("✂").size(50.0)).on_hover_text("Crop").clicked() {
-                        self.active_shape = false;
-                        self.crop = Some(crop_utils::Crop::new());
+                        self.start_crop();
                     }
                 });
 
 
+                if self.selected_shape == DrawingShape::Select {
+                    self.selection_properties_ui(ui);
+                }
+
                 if ui.button(RichText::new("🆑").size(50.0)).on_hover_text("Clear all").clicked() {
-                    self.last_actions = self.shapes.clone();
-                    self.shapes.clear();
+                    self.finish_pending_edits();
+                    self.clear_selection();
+                    let completed = self.shapes.len().saturating_sub(1);
+                    if completed > 0 {
+                        self.execute(Command::Clear { shapes: self.shapes[..completed].to_vec() });
+                    }
                 }
 
 
                 if self.shapes.is_empty() {
-                    self.shapes.push(DrawObj::new(vec![], self.stroke.clone()));
+                    self.shapes.push(DrawObj::new(vec![], self.stroke));
                 }
 
-                if self.shapes.len() > 1 {
-                    if ui.button(RichText::new("↩").size(50.0)).on_hover_text("Undo").clicked() {
-                        self.shapes.pop();
-                        self.last_actions.pop();
-                        
-                        self.last_actions.push(self.shapes.pop().unwrap());
-
-                        self.shapes.push(DrawObj::new( vec![], self.stroke));
-                        self.last_actions.push(DrawObj::new( vec![], self.stroke));
-                    }
+                if self.can_undo() && ui.button(RichText::new("↩").size(50.0)).on_hover_text("Undo").clicked() {
+                    self.undo();
                 }
 
-                if self.last_actions.len() > 1 {
-                    if ui.button(RichText::new("↪").size(50.0)).on_hover_text("Redo").clicked() {
-                        self.shapes.pop();
-                        self.last_actions.pop();
-
-                        self.shapes.push(self.last_actions.pop().unwrap());
-
-                        self.shapes.push(DrawObj::new( vec![], self.stroke));
-                        self.last_actions.push(DrawObj::new( vec![], self.stroke));
-                    }
+                if self.can_redo() && ui.button(RichText::new("↪").size(50.0)).on_hover_text("Redo").clicked() {
+                    self.redo();
                 }
+                ui.menu_button(RichText::new("🕘").size(50.0), |ui| self.history_ui(ui))
+                    .response
+                    .on_hover_text("History");
+                self.zoom_menu(ui);
                 if ui.button(RichText::new("🏠").size(50.0)).on_hover_text("Go back").clicked(){
                     *flag = false;
                     self.active_shape = false;
                 }
                 
             } else if self.crop.is_some() {
+                self.zoom_menu(ui);
                 if ui.button(RichText::new("✔").size(30.0)).clicked() {
-                    let cutrect = self.crop.clone().unwrap().get_cut_rect(Vec2::new(
-                        self.screenshot_image_buffer.clone().unwrap().width() as f32,
-                        self.screenshot_image_buffer.clone().unwrap().height() as f32,
-                    ));
-                    let result = imageops::crop(
-                        &mut self.screenshot_image_buffer.clone().unwrap(),
-                        cutrect.min.x.round() as u32,
-                        cutrect.min.y.round() as u32,
-                        cutrect.size().x.round() as u32,
-                        cutrect.size().y.round() as u32,
-                    )
-                    .to_image();
-                    self.screenshot_image_buffer = Some(result);
-                    self.painting_size(ui.available_size());
-                    if self.shapes.len() > 0 {
-                        self.shapes_remap(&self.crop.clone().unwrap());
-                        self.original_size = (self.screenshot_image_buffer.clone().unwrap().width(), self.screenshot_image_buffer.clone().unwrap().height());
-                    }
-                    self.active_shape = true;
-                    self.crop = None;
+                    self.apply_crop();
                 } else if ui.button(RichText::new("✖").size(30.0)).clicked() {
-                    self.active_shape = true;
-                    self.crop = None;
+                    self.cancel_crop();
+                } else if ui.button(RichText::new("⛶").size(30.0)).on_hover_text("Whole screenshot").clicked() {
+                    let image = self.screenshot_image_buffer.as_ref().unwrap();
+                    self.crop = Some(crop_utils::Crop::new(Vec2::new(image.width() as f32, image.height() as f32)));
+                }
+                if let Some(crop) = self.crop.as_mut() {
+                    crop.settings_ui(ui);
                 }
             }
         })
         .response
     }
 
+    /// Fit, 100% and 200% zoom presets.
+    fn zoom_menu(&mut self, ui: &mut egui::Ui) {
+        let size = if self.crop.is_some() { 30.0 } else { 50.0 };
+        ui.menu_button(RichText::new("🔍").size(size), |ui| {
+            let mut zoom = self.zoom;
+            if ui.selectable_value(&mut zoom, None, "Fit").clicked()
+                | ui.selectable_value(&mut zoom, Some(1.0), "100%").clicked()
+                | ui.selectable_value(&mut zoom, Some(2.0), "200%").clicked()
+            {
+                self.zoom = zoom;
+                self.pan = Vec2::ZERO;
+                ui.close_menu();
+            }
+        })
+        .response
+        .on_hover_text(format!("Zoom: {:.0}%", self.shown_zoom * 100.0));
+    }
+
+    /// Wheel and pinch zoom around the pointer, middle or Space + drag to pan. True while panning.
+    fn zoom_and_pan(&mut self, ui: &egui::Ui, response: &egui::Response, fit_zoom: f32) -> bool {
+        let space = ui.memory(|m| m.focus().is_none()) && ui.input(|i| i.key_down(egui::Key::Space));
+        let panning = response.dragged_by(egui::PointerButton::Middle) || (space && response.dragged());
+        if panning {
+            ui.ctx().set_cursor_icon(egui::CursorIcon::Grabbing);
+            self.zoom.get_or_insert(fit_zoom);
+            self.pan += response.drag_delta();
+        } else if space && response.hovered() {
+            ui.ctx().set_cursor_icon(egui::CursorIcon::Grab);
+        }
+
+        if let Some(pointer_pos) = response.hover_pos() {
+            let (zoom_delta, scroll) = ui.input(|i| (i.zoom_delta(), i.scroll_delta.y));
+            let factor = zoom_delta * (scroll / 200.0).exp();
+            if factor != 1.0 {
+                let old = self.zoom.unwrap_or(fit_zoom);
+                let new = (old * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+                // The pixel under the pointer stays where it is.
+                let center = response.rect.center() + self.pan;
+                self.pan = pointer_pos - response.rect.center() - (pointer_pos - center) * (new / old);
+                self.zoom = Some(new);
+            }
+        }
+        panning
+    }
+
     pub fn ui_content(&mut self, ui: &mut egui::Ui) -> egui::Response {
 
         if self.shapes.is_empty() {
             self.shapes.push(DrawObj::new( vec![], self.stroke));
         }
 
-        let painting_size = self.painting_size(ui.available_size());
-
+        let view_rect = self.view_rect();
         let (mut response, painter) =
-            ui.allocate_painter(painting_size.clone(), egui::Sense::drag());
-        self.ui_size = response.rect;
-        self.ui_position = response.rect.min;
-        
+            ui.allocate_painter(ui.available_size(), egui::Sense::drag());
+        let viewport = response.rect;
+
+        let pixels_per_point = ui.ctx().pixels_per_point();
+        let fit_zoom = (viewport.width() / view_rect.width()).min(viewport.height() / view_rect.height()) * pixels_per_point;
+        let panning = self.zoom_and_pan(ui, &response, fit_zoom);
+        if self.zoom.is_none() {
+            self.pan = Vec2::ZERO;
+        }
+        self.shown_zoom = self.zoom.unwrap_or(fit_zoom);
+        let max_pan = (view_rect.size() * self.shown_zoom / pixels_per_point + viewport.size()) / 2.0 - Vec2::splat(32.0);
+        self.pan = self.pan.clamp(-max_pan.max(Vec2::ZERO), max_pan.max(Vec2::ZERO));
+        let image_rect = Rect::from_center_size(viewport.center() + self.pan, view_rect.size() * self.shown_zoom / pixels_per_point);
+        self.ui_size = image_rect;
+        self.ui_position = image_rect.min;
+
+        // Only the cropped part of the texture is shown, the painter clips the annotations outside of the canvas.
+        let image = self.screenshot_image_buffer.as_ref().unwrap();
+        let image_size = Vec2::new(image.width() as f32, image.height() as f32);
         painter.add(egui::Shape::image(
             self.texture.as_ref().unwrap().id(),
-            egui::Rect::from_min_size(response.rect.min, painting_size.clone()), 
-            egui::Rect::from_min_max(egui::Pos2::ZERO, egui::Pos2::new(1., 1.)), 
+            image_rect,
+            Rect::from_min_max((view_rect.min.to_vec2() / image_size).to_pos2(), (view_rect.max.to_vec2() / image_size).to_pos2()),
             egui::Color32::WHITE,
         ));
 
-        self.to_screen = egui::emath::RectTransform::from_to(
-            egui::Rect::from_min_size(egui::Pos2::ZERO, response.rect.square_proportions()),
-            response.rect,
-        );
+        self.to_screen = egui::emath::RectTransform::from_to(view_rect, image_rect);
 
         let from_screen = self.to_screen.inverse();
 
         if self.shapes.is_empty() {
             self.shapes.push(DrawObj::new( vec![], self.stroke));
         }
-        if self.active_shape == true {
+        let current = self.shapes.last().unwrap();
+        if current.shape == DrawingShape::StraightLine && self.selected_shape != DrawingShape::StraightLine && !current.points.is_empty() {
+            self.commit_current_shape();
+        }
+        if !ui.input(|i| i.pointer.any_down()) {
+            self.commit_pending_edit();
+        }
+        if self.active_shape && !panning {
             match self.selected_shape {
-                DrawingShape::Line => {
+                DrawingShape::Line | DrawingShape::Highlighter => {
+                    let stroke = if self.selected_shape == DrawingShape::Highlighter { self.highlighter } else { self.stroke };
                     let current_line = self.shapes.last_mut().unwrap();
                     if let Some(pointer_pos) = response.interact_pointer_pos() {
-                        let canvas_pos = from_screen * pointer_pos;
-                        if current_line.points.last() != Some(&canvas_pos) {
-                            if current_line.stroke != self.stroke {
-                                current_line.stroke = self.stroke;
+                        let pixel_pos = from_screen * pointer_pos;
+                        if current_line.points.last() != Some(&pixel_pos) {
+                            if current_line.stroke != stroke {
+                                current_line.stroke = stroke;
                             }
-                            current_line.points.push(canvas_pos);
+                            current_line.shape = self.selected_shape.clone();
+                            current_line.points.push(pixel_pos);
                             response.mark_changed();
                         }
                     } else if !current_line.points.is_empty() {
-                        self.shapes.push(DrawObj::new( vec![], self.stroke));
+                        self.commit_current_shape();
                         response.mark_changed();
                     }
                 }
                 DrawingShape::StraightLine => {
                     let current_line = self.shapes.last_mut().unwrap();
-                    let mut next_canvas_pos = egui::Pos2::new(-1., -1.); 
+                    let mut next_pixel_pos = egui::Pos2::new(-1., -1.); 
 
                     if response.clicked() {
 
                         if let Some(pointer_pos) = response.hover_pos() {
 
-                            if current_line.points.last() != Some(&next_canvas_pos) {
+                            if current_line.points.last() != Some(&next_pixel_pos) {
                                 if current_line.stroke != self.stroke {
                                     current_line.stroke = self.stroke;
                                 }
-                                next_canvas_pos = from_screen * pointer_pos;
-                                current_line.points.push(next_canvas_pos);
+                                next_pixel_pos = from_screen * pointer_pos;
+                                current_line.shape = DrawingShape::StraightLine;
+                                current_line.points.push(next_pixel_pos);
                                 response.mark_changed();
                             }
                         }
 
                         if response.clicked() {
                             if let Some(pointer_pos) = response.interact_pointer_pos() {
-                                if current_line.points.last() != Some(&next_canvas_pos) {
+                                if current_line.points.last() != Some(&next_pixel_pos) {
                                     if current_line.stroke != self.stroke {
                                         current_line.stroke = self.stroke;
                                     }
-                                    next_canvas_pos = from_screen * pointer_pos;
-                                    current_line.points.push(next_canvas_pos);
+                                    next_pixel_pos = from_screen * pointer_pos;
+                                    current_line.points.push(next_pixel_pos);
                                     response.mark_changed();
                                 }
                             }
                         }
                     }
+                    // A double click ends the line, the next click starts a new one.
+                    if response.double_clicked() {
+                        self.commit_current_shape();
+                    }
+                }
+                DrawingShape::Select => {
+                    self.select_ui(&response);
+                }
+                // Images are pasted, not drawn.
+                DrawingShape::Image => {}
+                DrawingShape::Text => {
+                    if response.clicked() {
+                        if let Some(pointer_pos) = response.interact_pointer_pos() {
+                            self.edit_text_at(pointer_pos, from_screen * pointer_pos);
+                        }
+                    }
+                }
+                DrawingShape::Rectangle
+                | DrawingShape::Ellipse
+                | DrawingShape::Arrow
+                | DrawingShape::DoubleArrow
+                | DrawingShape::Redaction => {
+                    let constrain = ui.input(|i| i.modifiers.shift)
+                        && matches!(self.selected_shape, DrawingShape::Rectangle | DrawingShape::Ellipse);
+                    let current_shape = self.shapes.last_mut().unwrap();
+                    if let Some(pointer_pos) = response.interact_pointer_pos() {
+                        let pixel_pos = from_screen * pointer_pos;
+                        if current_shape.points.is_empty() {
+                            current_shape.shape = self.selected_shape.clone();
+                            current_shape.filled = self.fill_shape;
+                            current_shape.stroke = self.stroke;
+                            current_shape.redaction = Some((self.redaction_mode, self.redaction_strength));
+                            current_shape.points.push(pixel_pos);
+                        }
+                        let start = current_shape.points[0];
+                        current_shape.points.truncate(1);
+                        current_shape.points.push(if constrain { square_corner(start, pixel_pos) } else { pixel_pos });
+                        response.mark_changed();
+                    } else if !current_shape.points.is_empty() {
+                        // A click without dragging leaves nothing behind.
+                        if current_shape.points.len() < 2 || current_shape.is_degenerate() {
+                            current_shape.points.clear();
+                        } else {
+                            self.commit_current_shape();
+                        }
+                        response.mark_changed();
+                    }
                 }
             }
         }
-        let shapes = self
+        if self.selected_shape != DrawingShape::Text || !self.active_shape {
+            self.finish_text_editing();
+        }
+        if self.selected_shape != DrawingShape::Select || !self.active_shape {
+            self.clear_selection();
+        }
+        self.text_editor_ui(ui.ctx());
+        self.update_redactions();
+        self.update_overlay_textures(ui.ctx());
+
+        // The redaction being dragged is only outlined, it is applied once the mouse is released.
+        if let Some(current) = self.shapes.last().filter(|shape| shape.shape == DrawingShape::Redaction && shape.points.len() >= 2) {
+            let rect = self.to_screen.transform_rect(current.bounding_rect());
+            painter.rect_stroke(rect, 0.0, egui::Stroke::new(1.0, egui::Color32::GRAY));
+        }
+
+        let shapes: Vec<egui::Shape> = self
             .shapes
             .iter()
-            .filter(|line| line.points.len() >= 2)
-            .map(|line| {
-                let points: Vec<egui::Pos2> = line.points.iter().map(|p| self.to_screen * *p).collect();
-                egui::Shape::line(points, line.stroke)
-            });
+            .filter_map(|shape| match &shape.overlay {
+                Some(overlay) => Some(egui::Shape::image(
+                    self.overlay_textures.get(&overlay.id)?.id(),
+                    self.to_screen.transform_rect(shape.bounding_rect()),
+                    Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0)),
+                    egui::Color32::WHITE,
+                )),
+                None => shape.screen_shape(&painter, self.to_screen),
+            })
+            .collect();
         painter.extend(shapes);
+        if let Some(bounds) = self.selection.and_then(|index| self.shapes[index].bounds()) {
+            let rect = self.to_screen.transform_rect(bounds);
+            painter.add(egui::Shape::dashed_line(
+                &[rect.left_top(), rect.right_top(), rect.right_bottom(), rect.left_bottom(), rect.left_top()],
+                egui::Stroke::new(1.0, egui::Color32::GRAY),
+                4.0,
+                4.0,
+            ));
+            for (handle, _) in self.selection_handles() {
+                painter.rect(handle, 0.0, egui::Color32::WHITE, egui::Stroke::new(1.0, egui::Color32::DARK_GRAY));
+            }
+        }
+        if let Some(rect) = self.editing_text.and_then(|index| self.shapes[index].text_screen_rect(self.to_screen)) {
+            painter.add(egui::Shape::dashed_line(
+                &[rect.left_top(), rect.right_top(), rect.right_bottom(), rect.left_bottom(), rect.left_top()],
+                egui::Stroke::new(1.0, egui::Color32::GRAY),
+                4.0,
+                4.0,
+            ));
+        }
 
-        if self.crop.is_some() {
-            self.crop.as_mut().unwrap().crop_img(
-                ui,
-                response.clone(),
-                egui::Vec2::new(self.ui_size.width(), self.ui_size.height())
-            );
+        if let Some(crop) = self.crop.as_mut() {
+            crop.crop_img(ui, &response, self.to_screen, !panning);
         }
         response
     }
 
+    pub fn has_redactions(&self) -> bool {
+        !self.redactions().is_empty()
+    }
+
+    /// The screenshot with its redactions applied, before any annotation.
+    fn redacted_image(&self) -> RgbaImage {
+        let mut image = self.screenshot_image_buffer.clone().unwrap();
+        for (area, mode, strength) in self.redactions() {
+            image_utils::redact(&mut image, area, mode, strength);
+        }
+        image
+    }
+
     pub fn generate_rgba_image(&mut self) -> RgbaImage {
-        let mut output_image = self.screenshot_image_buffer.clone();
-
-        for line in self.shapes.clone().iter() {
-            for couple_points in line.points.windows(2) {
-                for offset in 0..= line.stroke.width as u8 {
-                    let mut start = self.segment_coordinates(&couple_points[0], (offset, offset));
-                    let mut end = self.segment_coordinates(&couple_points[1], (offset, offset));
-
-                    imageproc::drawing::draw_line_segment_mut(
-                        output_image.as_mut().unwrap(),
-                        start,
-                        end,
-                        image::Rgba(line.stroke.color.to_array()),
-                    );
+        // Redactions go first, so that no annotation is hidden by them, as in the preview.
+        let mut output_image = Some(self.redacted_image());
 
-                    start = self.segment_coordinates(&couple_points[0], (0, offset));
-                    end = self.segment_coordinates(&couple_points[1], (0, offset));
-                    imageproc::drawing::draw_line_segment_mut(
-                        output_image.as_mut().unwrap(),
-                        start,
-                        end,
-                        image::Rgba(line.stroke.color.to_array()),
-                    );
+        let image = output_image.as_mut().unwrap();
+        let mut mask = StrokeMask::new(image.width(), image.height());
+        for shape in &self.shapes {
+            if let Some(overlay) = &shape.overlay {
+                overlay.draw(image, shape.bounding_rect());
+                continue;
+            }
+            if let (Some(text_box), Some(point)) = (&shape.text, shape.points.first()) {
+                let position = *point;
+                if let Some(background) = text_box.background {
+                    let rect = text_box.pixel_rect(position);
+                    mask.fill_convex_polygon(&[rect.left_top(), rect.right_top(), rect.right_bottom(), rect.left_bottom()]);
+                    mask.composite(image, background);
+                    mask.clear();
                 }
+                text_utils::draw_text(&mut mask, &text_box.text, position.x, position.y, text_box.size);
+                mask.composite(image, shape.stroke.color);
+                mask.clear();
+                continue;
+            }
+            // Every part of a shape goes in the same mask, so that overlaps are blended once.
+            for primitive in shape.primitives() {
+                mask.add(&primitive);
             }
+            mask.composite(image, shape.stroke.color);
+            mask.clear();
+        }
+
+        let (x, y, width, height) = self.visible_area();
+        let output_image = output_image.unwrap();
+        if self.crop_area.is_some() {
+            imageops::crop_imm(&output_image, x, y, width, height).to_image()
+        } else {
+            output_image
+        }
+    }
+
+
+    /// The screenshot as an SVG document: the visible part of the capture, redactions applied, is embedded as PNG
+    /// and every annotation is a vector element on top of it, in pixels of the screenshot.
+    pub fn svg(&self) -> Result<String, YasaError> {
+        let (x, y, width, height) = self.visible_area();
+        let background = imageops::crop_imm(&self.redacted_image(), x, y, width, height).to_image();
+        let area = Rect::from_min_size(Pos2::new(x as f32, y as f32), Vec2::new(width as f32, height as f32));
+        let mut elements = vec![];
+        for shape in &self.shapes {
+            elements.extend(shape.svg_element()?);
         }
+        Ok(svg_utils::document(area, &image_utils::encode_png(&background)?, &elements))
+    }
+
+    /// Completed redactions, as pixel areas of the screenshot.
+    fn redactions(&self) -> Vec<(PixelArea, RedactionMode, u32)> {
+        let image = self.screenshot_image_buffer.as_ref().unwrap();
+        let image_rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(image.width() as f32, image.height() as f32));
+        let completed = &self.shapes[..self.shapes.len().saturating_sub(1)];
+        completed
+            .iter()
+            .filter(|shape| shape.shape == DrawingShape::Redaction && shape.points.len() >= 2)
+            .filter_map(|shape| {
+                let (mode, strength) = shape.redaction?;
+                let rect = shape.bounding_rect();
+                let rect = Rect::from_min_max(rect.min.round(), rect.max.round()).intersect(image_rect);
+                rect.is_positive().then_some((
+                    (rect.min.x as u32, rect.min.y as u32, rect.width() as u32, rect.height() as u32),
+                    mode,
+                    strength,
+                ))
+            })
+            .collect()
+    }
 
-        return output_image.unwrap().clone();
+    /// Uploads the pasted images that have no texture yet, and frees the ones no longer shown.
+    fn update_overlay_textures(&mut self, ctx: &egui::Context) {
+        let overlays: Vec<&Overlay> = self.shapes.iter().filter_map(|shape| shape.overlay.as_ref()).collect();
+        self.overlay_textures.retain(|id, _| overlays.iter().any(|overlay| overlay.id == *id));
+        for overlay in overlays {
+            self.overlay_textures.entry(overlay.id).or_insert_with(|| {
+                let options = egui::TextureOptions { magnification: egui::TextureFilter::Nearest, minification: egui::TextureFilter::Linear };
+                ctx.load_texture(format!("overlay_{}", overlay.id), image_utils::load_image_from_memory((*overlay.image).clone()), options)
+            });
+        }
+    }
+
+    /// Uploads the screenshot with its redactions applied whenever they change.
+    fn update_redactions(&mut self) {
+        let redactions = self.redactions();
+        if self.previewed_redactions.as_ref() == Some(&redactions) {
+            return;
+        }
+        let mut preview = self.screenshot_image_buffer.clone().unwrap();
+        for (area, mode, strength) in &redactions {
+            image_utils::redact(&mut preview, *area, *mode, *strength);
+        }
+        if let Some(texture) = self.texture.as_mut() {
+            // Sharp pixels when zoomed in, smooth ones when zoomed out.
+            let options = egui::TextureOptions { magnification: egui::TextureFilter::Nearest, minification: egui::TextureFilter::Linear };
+            texture.set(image_utils::load_image_from_memory(preview), options);
+        }
+        self.previewed_redactions = Some(redactions);
     }
+}
 
+/// Opposite corner of the square with one corner in `start` that fits in the drag to `end`.
+fn square_corner(start: Pos2, end: Pos2) -> Pos2 {
+    let delta = end - start;
+    let side = delta.x.abs().max(delta.y.abs());
+    start + Vec2::new(side.copysign(delta.x), side.copysign(delta.y))
+}
 
-    fn segment_coordinates(&mut self, point: &egui::Pos2, offset: (u8, u8)) -> (f32, f32) {
+/// Outline of the ellipse inscribed in `rect`.
+fn ellipse_points(rect: Rect) -> Vec<Pos2> {
+    const SEGMENTS: usize = 128;
+    (0..SEGMENTS)
+        .map(|i| {
+            let angle = i as f32 / SEGMENTS as f32 * std::f32::consts::TAU;
+            rect.center() + Vec2::new(angle.cos() * rect.width() / 2.0, angle.sin() * rect.height() / 2.0)
+        })
+        .collect()
+}
 
-        let w = self.screenshot_image_buffer.as_ref().unwrap().width();
-        let h = self.screenshot_image_buffer.as_ref().unwrap().height();
+/// Shaft and filled heads of an arrow pointing from `start` to `end`.
+/// The head grows with the stroke width so that it stays visible on thick arrows.
+fn arrow_primitives(start: Pos2, end: Pos2, width: f32, double: bool) -> Vec<Primitive> {
+    let length = start.distance(end);
+    if length <= 0.0 {
+        return vec![];
+    }
+    let dir = (end - start) / length;
+    let normal = dir.rot90();
+    let heads = if double { 2.0 } else { 1.0 };
+    let head_length = (3.0 * width + 8.0).min(length / heads);
+    let head_half_width = head_length * 0.6;
 
-        let output_size = egui::Vec2::new(w as f32, h as f32);
-        let rect_output_size = egui::Rect::from_min_size(egui::Pos2::ZERO, output_size);
+    let head = |tip: Pos2, dir: Vec2| {
+        let base = tip - dir * head_length;
+        Primitive::Fill(vec![tip, base + normal * head_half_width, base - normal * head_half_width])
+    };
+    let shaft_start = if double { start + dir * head_length } else { start };
+    let shaft_end = end - dir * head_length;
 
-        self.to_screen = egui::emath::RectTransform::from_to(
-            egui::Rect::from_min_size(egui::Pos2::ZERO, rect_output_size.square_proportions()),
-            rect_output_size,
-        );
+    let mut primitives = vec![Primitive::Stroke(vec![shaft_start, shaft_end], width), head(end, dir)];
+    if double {
+        primitives.push(head(start, -dir));
+    }
+    primitives
+}
 
-        let mut new_coordinates = self.to_screen * *point;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egui::{pos2, Color32};
+    use image::Rgba;
+    use std::path::PathBuf;
 
-        new_coordinates = new_coordinates.add(egui::Vec2::new(
-            self.stroke.width / 2. + offset.0 as f32,
-            self.stroke.width / 2. + offset.1 as f32,
-        ));
+    /// Set to write the reference images again, after checking that the new output is right.
+    const UPDATE_GOLDEN: &str = "YASA_UPDATE_GOLDEN";
 
-        if new_coordinates.x > output_size.x {
-            new_coordinates.x = output_size.x - 1.;
-        } else if new_coordinates.x < 0. {
-            new_coordinates.x = 0.;
-        }
+    fn golden_path(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/app/testdata/golden").join(format!("{}.png", name))
+    }
 
-        if new_coordinates.y > output_size.y {
-            new_coordinates.y = output_size.y - 1.;
-        } else if new_coordinates.y < 0. {
-            new_coordinates.y = 0.;
+    /// Compares `image` pixel by pixel with the reference PNG `name`.
+    fn assert_golden(name: &str, image: &RgbaImage) {
+        let path = golden_path(name);
+        if std::env::var_os(UPDATE_GOLDEN).is_some() {
+            image.save(&path).unwrap();
+            return;
+        }
+        let expected = image::open(&path)
+            .unwrap_or_else(|e| panic!("{}: {} (run the tests with {}=1 to create it)", path.display(), e, UPDATE_GOLDEN))
+            .to_rgba8();
+        assert_eq!(expected.dimensions(), image.dimensions(), "size of {}", name);
+        let differences: Vec<(u32, u32)> = image
+            .enumerate_pixels()
+            .filter(|(x, y, pixel)| expected.get_pixel(*x, *y) != *pixel)
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        if let Some((x, y)) = differences.first() {
+            let actual = std::env::temp_dir().join(format!("yasa-golden-{}.png", name));
+            image.save(&actual).unwrap();
+            panic!(
+                "{} pixels of {} differ from {}, the first at ({}, {}): expected {:?}, got {:?}. Output written to {}",
+                differences.len(),
+                name,
+                path.display(),
+                x,
+                y,
+                expected.get_pixel(*x, *y).0,
+                image.get_pixel(*x, *y).0,
+                actual.display()
+            );
         }
+    }
+
+    /// Grey checkerboard, so that translucent colours and anti-aliasing show against both shades.
+    fn background() -> RgbaImage {
+        RgbaImage::from_fn(64, 48, |x, y| {
+            let shade = if (x / 8 + y / 8) % 2 == 0 { 200 } else { 120 };
+            Rgba([shade, shade, shade, 255])
+        })
+    }
+
+    fn shape(kind: DrawingShape, points: &[Pos2], width: f32, color: Color32) -> DrawObj {
+        DrawObj { shape: kind, ..DrawObj::new(points.to_vec(), egui::Stroke::new(width, color)) }
+    }
+
+    /// Editor showing `image` with `shapes` drawn, followed like in the editor by the shape about to be drawn.
+    fn painting(image: RgbaImage, mut shapes: Vec<DrawObj>) -> Painting {
+        let mut painting = Painting::new(None, Some(image));
+        shapes.push(DrawObj::default());
+        painting.shapes = shapes;
+        painting
+    }
+
+    fn render(shapes: Vec<DrawObj>) -> RgbaImage {
+        painting(background(), shapes).generate_rgba_image()
+    }
+
+    const BLUE: Color32 = Color32::from_rgb(18, 160, 215);
+    const RED: Color32 = Color32::from_rgb(220, 30, 30);
+
+    #[test]
+    fn freehand_line() {
+        let points = [pos2(6.0, 40.0), pos2(14.0, 10.0), pos2(30.0, 30.0), pos2(45.5, 8.5), pos2(58.0, 38.0)];
+        assert_golden("line", &render(vec![shape(DrawingShape::Line, &points, 3.0, BLUE)]));
+    }
+
+    #[test]
+    fn thick_straight_line_has_round_caps() {
+        let points = [pos2(12.0, 36.0), pos2(52.0, 12.0)];
+        assert_golden("straight_line_thick", &render(vec![shape(DrawingShape::StraightLine, &points, 12.0, RED)]));
+    }
+
+    #[test]
+    fn round_caps_of_dots_and_short_segments() {
+        let shapes = vec![
+            shape(DrawingShape::Line, &[pos2(12.0, 24.0), pos2(12.0, 24.0)], 10.0, RED),
+            shape(DrawingShape::Line, &[pos2(28.3, 20.7), pos2(33.1, 27.2)], 9.0, BLUE),
+            shape(DrawingShape::Line, &[pos2(48.0, 12.0), pos2(52.0, 12.0)], 1.0, RED),
+            shape(DrawingShape::Line, &[pos2(48.0, 36.0), pos2(50.0, 36.0)], 5.0, RED),
+        ];
+        assert_golden("round_caps", &render(shapes));
+    }
+
+    #[test]
+    fn rectangles() {
+        let outline = shape(DrawingShape::Rectangle, &[pos2(4.0, 4.0), pos2(30.0, 40.0)], 4.0, BLUE);
+        let filled = DrawObj { filled: true, ..shape(DrawingShape::Rectangle, &[pos2(58.5, 10.5), pos2(36.2, 43.7)], 4.0, RED) };
+        assert_golden("rectangles", &render(vec![outline, filled]));
+    }
+
+    #[test]
+    fn ellipses() {
+        let outline = shape(DrawingShape::Ellipse, &[pos2(3.0, 6.0), pos2(33.0, 42.0)], 3.0, BLUE);
+        let filled = DrawObj { filled: true, ..shape(DrawingShape::Ellipse, &[pos2(36.0, 12.0), pos2(61.0, 36.0)], 3.0, RED) };
+        assert_golden("ellipses", &render(vec![outline, filled]));
+    }
+
+    #[test]
+    fn arrows() {
+        let shapes = vec![
+            shape(DrawingShape::Arrow, &[pos2(6.0, 40.0), pos2(40.0, 8.0)], 3.0, BLUE),
+            shape(DrawingShape::DoubleArrow, &[pos2(20.0, 42.0), pos2(60.0, 30.0)], 2.0, RED),
+        ];
+        assert_golden("arrows", &render(shapes));
+    }
+
+    #[test]
+    fn translucent_stroke_is_blended_once_where_it_crosses_itself() {
+        let color = Color32::from_rgba_unmultiplied(0, 90, 255, 128);
+        let points = [pos2(6.0, 6.0), pos2(58.0, 42.0), pos2(58.0, 6.0), pos2(6.0, 42.0)];
+        assert_golden("translucent_line", &render(vec![shape(DrawingShape::Line, &points, 8.0, color)]));
+    }
+
+    #[test]
+    fn translucent_arrow_is_blended_once_where_the_head_meets_the_shaft() {
+        let color = Color32::from_rgba_unmultiplied(220, 30, 30, 100);
+        let arrow = shape(DrawingShape::Arrow, &[pos2(8.0, 24.0), pos2(58.0, 24.0)], 6.0, color);
+        assert_golden("translucent_arrow", &render(vec![arrow]));
+    }
 
-        return [new_coordinates.x, new_coordinates.y].into();
+    #[test]
+    fn highlighter_strokes_overlap() {
+        let color = Color32::from_rgba_unmultiplied(255, 230, 0, 96);
+        let shapes = vec![
+            // Doubles back on itself: one highlight.
+            shape(DrawingShape::Highlighter, &[pos2(4.0, 14.0), pos2(60.0, 14.0), pos2(20.0, 18.0)], 16.0, color),
+            // Crosses the first one: two highlights on top of each other.
+            shape(DrawingShape::Highlighter, &[pos2(32.0, 2.0), pos2(32.0, 46.0)], 16.0, color),
+        ];
+        assert_golden("highlighter_overlap", &render(shapes));
     }
 
-    pub fn painting_size(&mut self, ui_available_size: egui::Vec2) -> egui::Vec2 {
-        let mut painting_size = egui::Vec2::ZERO;
-        if ui_available_size.x < ui_available_size.y && self.aspect_ratio >= 1. {
-            painting_size =
-                egui::Vec2::from([ui_available_size.x, ui_available_size.x / self.aspect_ratio]);
-        } else if ui_available_size.x > ui_available_size.y {
-            painting_size =
-                egui::Vec2::from([ui_available_size.y * self.aspect_ratio, ui_available_size.y]);
+    #[test]
+    fn text_with_background() {
+        let text = DrawObj {
+            shape: DrawingShape::Text,
+            text: Some(TextBox { text: "Yasa\n42".to_string(), size: 14.0, background: Some(Color32::WHITE) }),
+            ..DrawObj::new(vec![pos2(8.0, 6.0)], egui::Stroke::new(1.0, RED))
         };
+        assert_golden("text", &render(vec![text]));
+    }
 
-        return painting_size;
+    #[test]
+    fn translucent_text_blends_with_an_opaque_background() {
+        let text = DrawObj {
+            shape: DrawingShape::Text,
+            text: Some(TextBox { text: "Yasa".to_string(), size: 24.0, background: None }),
+            ..DrawObj::new(vec![pos2(4.0, 8.0)], egui::Stroke::new(1.0, Color32::from_rgba_unmultiplied(0, 0, 255, 128)))
+        };
+        let image = render(vec![text]);
+        assert!(image.pixels().all(|pixel| pixel.0[3] == 255), "the text left see-through pixels");
+        assert_golden("translucent_text", &image);
     }
 
-    pub fn shapes_remap(&mut self, crop: &crop_utils::Crop) { 
-        for shape in &mut self.shapes {
-            let points: Vec<Pos2> = shape.points.iter_mut().map(|p| {
-                let mut point =  *p;
-                point.x -= crop.offset_x_left;
-                point.y -= crop.offset_y_up;
+    #[test]
+    fn redactions() {
+        let gradient = RgbaImage::from_fn(64, 48, |x, y| Rgba([(x * 4) as u8, (y * 5) as u8, (x * y % 256) as u8, 255]));
+        let redaction = |mode, strength, points: &[Pos2]| DrawObj {
+            redaction: Some((mode, strength)),
+            ..shape(DrawingShape::Redaction, points, 1.0, RED)
+        };
+        let shapes = vec![
+            redaction(RedactionMode::Pixelate, 6, &[pos2(2.0, 2.0), pos2(30.0, 40.0)]),
+            redaction(RedactionMode::Blur, 4, &[pos2(34.0, 8.0), pos2(62.0, 46.0)]),
+        ];
+        assert_golden("redactions", &painting(gradient, shapes).generate_rgba_image());
+    }
 
-                return point;
-            }).collect();
+    #[test]
+    fn project_can_hide_the_redacted_pixels() {
+        let redaction = DrawObj {
+            redaction: Some((RedactionMode::Pixelate, 16)),
+            ..shape(DrawingShape::Redaction, &[pos2(4.0, 4.0), pos2(20.0, 20.0)], 1.0, RED)
+        };
+        let painting = painting(background(), vec![redaction]);
+        assert!(painting.has_redactions());
+        assert_eq!(painting.project(false).image, background());
 
-            shape.points = points;
-        }
+        let baked = painting.project(true);
+        assert_ne!(baked.image.get_pixel(4, 4), background().get_pixel(4, 4));
+        assert_eq!(baked.image.get_pixel(24, 24), background().get_pixel(24, 24));
+        assert_eq!(baked.shapes.len(), 1);
+    }
+
+    /// Images embedded in `svg`, in order.
+    fn svg_images(svg: &str) -> Vec<RgbaImage> {
+        use base64::Engine;
+        svg.split("data:image/png;base64,")
+            .skip(1)
+            .map(|rest| {
+                let png = base64::engine::general_purpose::STANDARD.decode(&rest[..rest.find('"').unwrap()]).unwrap();
+                image_utils::decode_png(&png).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn svg_shows_the_cropped_area() {
+        let mut painting = painting(background(), vec![shape(DrawingShape::Line, &[pos2(20.0, 10.0), pos2(30.0, 20.5)], 3.0, BLUE)]);
+        painting.crop_area = Some((16, 8, 32, 24));
+        let svg = painting.svg().unwrap();
+        assert!(svg.contains(r#"width="32" height="24" viewBox="16 8 32 24""#), "{}", svg);
+        // Annotations keep the coordinates of the whole screenshot.
+        assert!(svg.contains(r#"points="20,10 30,20.5""#), "{}", svg);
+
+        let images = svg_images(&svg);
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0], imageops::crop_imm(&background(), 16, 8, 32, 24).to_image());
+    }
+
+    #[test]
+    fn svg_gives_each_shape_its_colour() {
+        let translucent = Color32::from_rgba_unmultiplied(255, 0, 0, 128);
+        let shapes = vec![
+            shape(DrawingShape::Line, &[pos2(1.0, 1.0), pos2(9.0, 9.0)], 2.0, Color32::from_rgb(0, 0, 255)),
+            shape(DrawingShape::Highlighter, &[pos2(1.0, 20.0), pos2(40.0, 20.0)], 16.0, translucent),
+            DrawObj { filled: true, ..shape(DrawingShape::Rectangle, &[pos2(2.0, 30.0), pos2(12.0, 40.0)], 2.0, Color32::from_rgb(0, 255, 0)) },
+        ];
+        let svg = painting(background(), shapes).svg().unwrap();
+        assert!(svg.contains(r##"stroke="#0000ff" stroke-width="2""##), "{}", svg);
+        assert!(svg.contains(r##"stroke="#ff0000" stroke-opacity="0.502" stroke-width="16""##), "{}", svg);
+        assert!(svg.contains(r##"<rect x="2" y="30" width="10" height="10" fill="#00ff00"/>"##), "{}", svg);
+    }
+
+    #[test]
+    fn svg_escapes_text() {
+        let text = DrawObj {
+            shape: DrawingShape::Text,
+            text: Some(TextBox { text: "<b> & \"q\"".to_string(), size: 12.0, background: None }),
+            ..DrawObj::new(vec![pos2(4.0, 4.0)], egui::Stroke::new(1.0, RED))
+        };
+        let svg = painting(background(), vec![text]).svg().unwrap();
+        assert!(svg.contains("&lt;b&gt; &amp; &quot;q&quot;"), "{}", svg);
+        assert!(!svg.contains("<b>"), "{}", svg);
+    }
+
+    #[test]
+    fn svg_embeds_the_redacted_capture_and_the_pasted_images() {
+        let picture = RgbaImage::from_pixel(3, 2, Rgba([1, 2, 3, 255]));
+        let shapes = vec![
+            DrawObj {
+                redaction: Some((RedactionMode::Pixelate, 16)),
+                ..shape(DrawingShape::Redaction, &[pos2(4.0, 4.0), pos2(20.0, 20.0)], 1.0, RED)
+            },
+            DrawObj {
+                shape: DrawingShape::Image,
+                overlay: Some(Overlay::new(picture.clone())),
+                ..DrawObj::new(vec![pos2(30.0, 10.0), pos2(36.0, 14.0)], egui::Stroke::NONE)
+            },
+        ];
+        let svg = painting(background(), shapes).svg().unwrap();
+        assert!(svg.contains(r#"<image x="30" y="10" width="6" height="4""#), "{}", svg);
+        let images = svg_images(&svg);
+        assert_eq!(images.len(), 2);
+        assert_ne!(images[0].get_pixel(4, 4), background().get_pixel(4, 4), "the redacted pixels are in the SVG");
+        assert_eq!(images[1], picture);
+    }
+
+    #[test]
+    fn pasted_image_is_scaled_to_its_box() {
+        let picture = RgbaImage::from_fn(8, 4, |x, _| if x < 4 { Rgba([255, 0, 0, 255]) } else { Rgba([0, 0, 255, 128]) });
+        let image = DrawObj {
+            shape: DrawingShape::Image,
+            overlay: Some(Overlay::new(picture)),
+            ..DrawObj::new(vec![pos2(10.0, 10.0), pos2(50.0, 30.0)], egui::Stroke::NONE)
+        };
+        assert_golden("image", &render(vec![image]));
+    }
+
+    #[test]
+    fn an_empty_image_cannot_be_cropped() {
+        let mut painting = painting(RgbaImage::new(0, 0), vec![]);
+        painting.crop = Some(crop_utils::Crop::new(Vec2::new(10.0, 10.0)));
+        painting.apply_crop();
+        assert_eq!(painting.crop_area, None);
+        assert!(!painting.history.can_undo());
+    }
+
+    #[test]
+    fn annotations_outside_of_the_crop_are_cut() {
+        let mut painting = painting(background(), vec![shape(DrawingShape::StraightLine, &[pos2(0.0, 0.0), pos2(64.0, 48.0)], 6.0, BLUE)]);
+        painting.crop_area = Some((16, 8, 32, 24));
+        assert_golden("cropped", &painting.generate_rgba_image());
     }
 }
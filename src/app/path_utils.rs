@@ -1,10 +1,14 @@
 use crate::app::save_utils::check_filename;
+use crate::app::save_utils::generate_filename;
 use crate::app::save_utils::SavePath;
 use crate::app::ImgFormats;
+use crate::app::pdf_utils::PageSize;
+use crate::app::project_utils;
 use egui::RichText;
 use egui::Vec2;
 use egui::{CollapsingHeader, Color32, ComboBox, ScrollArea, Ui};
 use std::fs;
+use std::path::{Path, PathBuf};
 use image::RgbaImage;
 
 
@@ -21,7 +25,25 @@ pub fn ui_settings(ui: &mut Ui, path: &mut SavePath, screenshot: &Option<RgbaIma
             ui.selectable_value(&mut path.format, ImgFormats::PNG, "PNG");
             ui.selectable_value(&mut path.format, ImgFormats::JPEG, "JPEG");
             ui.selectable_value(&mut path.format, ImgFormats::GIF, "GIF");
+            ui.selectable_value(&mut path.format, ImgFormats::YASA, "YASA (editable project)");
+            ui.selectable_value(&mut path.format, ImgFormats::SVG, "SVG (vector annotations)");
+            ui.selectable_value(&mut path.format, ImgFormats::PDF, "PDF");
         });
+    if path.format == ImgFormats::PDF {
+        ui.horizontal(|ui| {
+            ui.label("Page");
+            ui.selectable_value(&mut path.page_size, PageSize::Image, "Image size");
+            ui.selectable_value(&mut path.page_size, PageSize::A4, "A4")
+                .on_hover_text("Centred, and shrunk if it does not fit");
+        });
+    }
+    if path.format == ImgFormats::YASA {
+        ui.checkbox(&mut path.bake_redactions, "Hide redacted pixels")
+            .on_hover_text("Apply the redactions to the capture stored in the project");
+        if !path.bake_redactions {
+            ui.colored_label(Color32::YELLOW, "⚠ Redacted areas can be recovered from the project file");
+        }
+    }
     ui.end_row();
 
     ui.allocate_space(Vec2::new(0.0, 15.0));
@@ -30,11 +52,22 @@ pub fn ui_settings(ui: &mut Ui, path: &mut SavePath, screenshot: &Option<RgbaIma
 
     ui.allocate_space(Vec2::new(0.0, 15.0));
 
+    ui.label(RichText::new("Filename template").size(15.0));
+    ui.text_edit_singleline(&mut path.name_template)
+        .on_hover_text("Date and time placeholders, e.g. %Y-%m-%d_%H%M%S");
+    ui.label(format!("Next capture: {}", generate_filename(&path.name_template)));
+
+    ui.allocate_space(Vec2::new(0.0, 15.0));
+
+    ui.separator();
+
+    ui.allocate_space(Vec2::new(0.0, 15.0));
+
     let start_tree = path.path.clone();
     let scroll = ScrollArea::new([false, true]);
     ui.label(RichText::new(format!(
         "Destination Path: {}",
-        path.path.clone().into_os_string().into_string().unwrap()
+        path.path.display()
     )).size(15.0));
 
     ui.allocate_space(Vec2::new(0.0, 5.0));
@@ -62,20 +95,17 @@ pub fn ui_settings(ui: &mut Ui, path: &mut SavePath, screenshot: &Option<RgbaIma
                     )).size(15.0))
                     .default_open(true)
                     .show(ui, |ui| {
-                        if let Ok(entries) = fs::read_dir(&start_tree.clone()) {
-                            for entry in entries {
-                                if let Ok(entry) = entry {
-                                    if entry.path().is_dir() {
-                                        if ui
-                                            .button(RichText::new(format!(
-                                                "🗁 {}",
-                                                entry.file_name().to_string_lossy()
-                                            )).size(15.0))
-                                            .clicked()
-                                        {
-                                            path.path = entry.path().to_path_buf();
-                                        }
-                                    }
+                        if let Ok(entries) = fs::read_dir(&start_tree) {
+                            for entry in entries.flatten() {
+                                if entry.path().is_dir()
+                                    && ui
+                                        .button(RichText::new(format!(
+                                            "🗁 {}",
+                                            entry.file_name().to_string_lossy()
+                                        )).size(15.0))
+                                        .clicked()
+                                {
+                                    path.path = entry.path().to_path_buf();
                                 }
                             }
                         }
@@ -108,3 +138,67 @@ pub fn ui_settings(ui: &mut Ui, path: &mut SavePath, screenshot: &Option<RgbaIma
     ui.allocate_space(Vec2::new(0.0, 15.0));
 
 }
+
+/// Extensions of the image formats this build can decode, see the `image` features in Cargo.toml.
+const IMAGE_EXTENSIONS: [&str; 4] = ["png", "jpg", "jpeg", "gif"];
+
+/// True for the images and projects that can be opened in the editor.
+pub fn can_open(path: &Path) -> bool {
+    let Some(extension) = path.extension().and_then(|extension| extension.to_str()) else {
+        return false;
+    };
+    extension.eq_ignore_ascii_case(project_utils::PROJECT_EXTENSION)
+        || IMAGE_EXTENSIONS.iter().any(|image| extension.eq_ignore_ascii_case(image))
+}
+
+/// Browses the folders from `folder`, listing the files that can be opened. Returns the file clicked.
+pub fn ui_open_file(ui: &mut Ui, folder: &mut PathBuf) -> Option<PathBuf> {
+    let mut opened = None;
+    let start_tree = folder.clone();
+
+    ui.label(RichText::new(format!("Folder: {}", folder.display())).size(15.0));
+    ui.allocate_space(Vec2::new(0.0, 5.0));
+
+    ScrollArea::new([false, true]).show(ui, |ui| {
+        if let Some(parent_dir) = start_tree.parent() {
+            let name = parent_dir.file_name().map_or("/".into(), |name| name.to_string_lossy());
+            if ui.button(RichText::new(format!("🗁 {}", name)).size(15.0)).clicked() {
+                *folder = parent_dir.to_path_buf();
+            }
+        }
+        let Ok(entries) = fs::read_dir(&start_tree) else {
+            ui.label(RichText::new("This folder cannot be read").size(15.0).color(Color32::LIGHT_RED));
+            return;
+        };
+        let mut entries: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+        entries.sort();
+        for path in entries.iter().filter(|path| path.is_dir()) {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            if ui.button(RichText::new(format!("🗁 {}", name)).size(15.0)).clicked() {
+                *folder = path.clone();
+            }
+        }
+        for path in entries.iter().filter(|path| path.is_file() && can_open(path)) {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            if ui.button(RichText::new(format!("🖻 {}", name)).size(15.0)).clicked() {
+                opened = Some(path.clone());
+            }
+        }
+    });
+    opened
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_open_the_formats_of_this_build_only() {
+        for name in ["shot.png", "shot.JPG", "shot.jpeg", "shot.gif", "notes.yasa", "notes.YASA"] {
+            assert!(can_open(Path::new(name)), "{}", name);
+        }
+        for name in ["shot.webp", "shot.bmp", "shot.tiff", "shot.ico", "shot.svg", "shot", "png"] {
+            assert!(!can_open(Path::new(name)), "{}", name);
+        }
+    }
+}
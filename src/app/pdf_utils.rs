@@ -0,0 +1,278 @@
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use image::{DynamicImage, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use super::error_utils::YasaError;
+use super::save_utils::{self, SavePath};
+
+pub const PDF_EXTENSION: &str = "pdf";
+
+/// Points (1/72 inch) per pixel: captures are shown at 96 dpi, the usual density of a screen.
+const POINTS_PER_PIXEL: f32 = 0.75;
+/// Portrait A4, in points.
+const A4: (f32, f32) = (595.28, 841.89);
+/// Blank border around an image fitted in an A4 page, in points.
+const A4_MARGIN: f32 = 36.0;
+
+/// Size of the page of each image.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PageSize {
+    /// The page is exactly as large as the image.
+    #[default]
+    Image,
+    /// The image is centred in an A4 page, turned to landscape for wide images, and shrunk to fit if needed.
+    A4,
+}
+
+/// Writes one page per image to the folder and name in `save_path`, returning the full path written.
+pub fn save_pdf(save_path: &SavePath, pages: impl IntoIterator<Item = Result<RgbaImage, YasaError>>) -> Result<PathBuf, YasaError> {
+    let pathname = save_utils::target_path(save_path, PDF_EXTENSION)?;
+    let mut writer = BufWriter::new(File::create(&pathname)?);
+    write_pdf(&mut writer, pages, save_path.page_size)?;
+    writer.flush()?;
+    Ok(pathname)
+}
+
+/// Writes a PDF document with one page per image. Images are stored losslessly, without their alpha channel.
+/// They are taken one at a time, so that only one of them needs to be in memory.
+pub fn write_pdf<W: Write>(writer: W, pages: impl IntoIterator<Item = Result<RgbaImage, YasaError>>, page_size: PageSize) -> Result<(), YasaError> {
+    let mut pdf = PdfWriter::new(writer)?;
+    // Objects 1 and 2 are the catalog and the page tree, each page then takes three: page, content and image.
+    pdf.object(1, b"<< /Type /Catalog /Pages 2 0 R >>")?;
+    let mut kids = vec![];
+    for (i, image) in pages.into_iter().enumerate() {
+        let image = image?;
+        let page_id = 3 + 3 * i;
+        kids.push(format!("{} 0 R", page_id));
+        let (content_id, image_id) = (page_id + 1, page_id + 2);
+        let (page, placement) = layout(image.width(), image.height(), page_size);
+        pdf.object(
+            page_id,
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.2} {:.2}] /Resources << /XObject << /Im0 {} 0 R >> >> /Contents {} 0 R >>",
+                page.0, page.1, image_id, content_id
+            )
+            .as_bytes(),
+        )?;
+        let (x, y, width, height) = placement;
+        let content = format!("q {:.2} 0 0 {:.2} {:.2} {:.2} cm /Im0 Do Q", width, height, x, y);
+        pdf.stream(content_id, "", content.as_bytes())?;
+
+        let (width, height) = image.dimensions();
+        let rgb = DynamicImage::ImageRgba8(image).to_rgb8();
+        let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+        encoder.write_all(rgb.as_raw())?;
+        let dictionary = format!(
+            "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceRGB /BitsPerComponent 8 /Filter /FlateDecode",
+            width, height
+        );
+        pdf.stream(image_id, &dictionary, &encoder.finish()?)?;
+    }
+    // The page tree goes last, once the number of pages is known.
+    pdf.object(2, format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), kids.len()).as_bytes())?;
+    pdf.finish(1)
+}
+
+/// Size of the page, and position and size of the image on it (from the bottom-left corner), in points.
+fn layout(width: u32, height: u32, page_size: PageSize) -> ((f32, f32), (f32, f32, f32, f32)) {
+    let (width, height) = (width as f32 * POINTS_PER_PIXEL, height as f32 * POINTS_PER_PIXEL);
+    match page_size {
+        PageSize::Image => ((width, height), (0.0, 0.0, width, height)),
+        PageSize::A4 => {
+            let (page_width, page_height) = if width > height { (A4.1, A4.0) } else { A4 };
+            let scale = ((page_width - 2.0 * A4_MARGIN) / width).min((page_height - 2.0 * A4_MARGIN) / height).min(1.0);
+            let (width, height) = (width * scale, height * scale);
+            let (x, y) = ((page_width - width) / 2.0, (page_height - height) / 2.0);
+            ((page_width, page_height), (x, y, width, height))
+        }
+    }
+}
+
+/// Writes numbered objects, remembering where each one starts for the cross-reference table.
+struct PdfWriter<W: Write> {
+    writer: W,
+    written: usize,
+    /// Byte offset of every object, by number minus one.
+    offsets: Vec<usize>,
+}
+
+impl<W: Write> PdfWriter<W> {
+    fn new(writer: W) -> Result<Self, YasaError> {
+        let mut pdf = Self { writer, written: 0, offsets: vec![] };
+        // The comment with bytes above 127 tells transfer tools that the file is binary.
+        pdf.write(b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n")?;
+        Ok(pdf)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), YasaError> {
+        self.writer.write_all(bytes)?;
+        self.written += bytes.len();
+        Ok(())
+    }
+
+    fn start_object(&mut self, id: usize) -> Result<(), YasaError> {
+        if self.offsets.len() < id {
+            self.offsets.resize(id, 0);
+        }
+        self.offsets[id - 1] = self.written;
+        self.write(format!("{} 0 obj\n", id).as_bytes())
+    }
+
+    fn object(&mut self, id: usize, body: &[u8]) -> Result<(), YasaError> {
+        self.start_object(id)?;
+        self.write(body)?;
+        self.write(b"\nendobj\n")
+    }
+
+    /// Stream object, `dictionary` holding the entries other than its length.
+    fn stream(&mut self, id: usize, dictionary: &str, data: &[u8]) -> Result<(), YasaError> {
+        self.start_object(id)?;
+        let entries = format!("{} /Length {}", dictionary, data.len());
+        self.write(format!("<< {} >>\nstream\n", entries.trim_start()).as_bytes())?;
+        self.write(data)?;
+        self.write(b"\nendstream\nendobj\n")
+    }
+
+    /// Writes the cross-reference table and the trailer pointing to the `root` catalog.
+    fn finish(mut self, root: usize) -> Result<(), YasaError> {
+        let xref = self.written;
+        let mut table = format!("xref\n0 {}\n0000000000 65535 f \n", self.offsets.len() + 1);
+        for offset in &self.offsets {
+            table.push_str(&format!("{:010} 00000 n \n", offset));
+        }
+        table.push_str(&format!(
+            "trailer\n<< /Size {} /Root {} 0 R >>\nstartxref\n{}\n%%EOF\n",
+            self.offsets.len() + 1,
+            root,
+            xref
+        ));
+        self.write(table.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::ZlibDecoder;
+    use image::Rgba;
+    use std::io::Read;
+
+    fn pdf(pages: &[RgbaImage], page_size: PageSize) -> Vec<u8> {
+        let mut bytes = vec![];
+        write_pdf(&mut bytes, pages.iter().cloned().map(Ok), page_size).unwrap();
+        bytes
+    }
+
+    fn find(bytes: &[u8], pattern: &str) -> Option<usize> {
+        bytes.windows(pattern.len()).position(|window| window == pattern.as_bytes())
+    }
+
+    /// Text of `bytes` from `start` up to, without, the next `end`.
+    fn text_until(bytes: &[u8], start: usize, end: &str) -> String {
+        let length = find(&bytes[start..], end).unwrap();
+        String::from_utf8_lossy(&bytes[start..start + length]).to_string()
+    }
+
+    /// Byte offset of every object, read back through the trailer and the cross-reference table.
+    fn object_offsets(bytes: &[u8]) -> Vec<usize> {
+        let startxref = bytes.windows(9).rposition(|window| window == b"startxref").unwrap();
+        let xref: usize = text_until(bytes, startxref + "startxref\n".len(), "\n").parse().unwrap();
+        assert!(bytes[xref..].starts_with(b"xref\n0 "), "startxref does not point to the table");
+        let table = text_until(bytes, xref, "trailer");
+        let mut lines = table.lines().skip(1);
+        let count: usize = lines.next().unwrap().split(' ').nth(1).unwrap().parse().unwrap();
+        assert_eq!(lines.next(), Some("0000000000 65535 f "));
+        let offsets: Vec<usize> = lines.map(|line| line[..10].parse().unwrap()).collect();
+        assert_eq!(offsets.len() + 1, count);
+        assert!(text_until(bytes, xref, "startxref").contains(&format!("/Size {} ", count)));
+        offsets
+    }
+
+    /// Checks that every object is where the table says, and returns the dictionary of each one.
+    fn objects(bytes: &[u8]) -> Vec<String> {
+        object_offsets(bytes)
+            .iter()
+            .enumerate()
+            .map(|(i, offset)| {
+                let header = format!("{} 0 obj\n", i + 1);
+                assert!(bytes[*offset..].starts_with(header.as_bytes()), "object {} is not at {}", i + 1, offset);
+                text_until(bytes, offset + header.len(), "\n")
+            })
+            .collect()
+    }
+
+    /// Data of the stream of the object whose dictionary is `dictionary`, checked against its length.
+    fn stream_data<'a>(bytes: &'a [u8], dictionary: &str) -> &'a [u8] {
+        let start = find(bytes, dictionary).unwrap() + dictionary.len() + "\nstream\n".len();
+        let length: usize = dictionary.rsplit("/Length ").next().unwrap().trim_end_matches(" >>").parse().unwrap();
+        assert!(bytes[start + length..].starts_with(b"\nendstream"), "wrong stream length in {}", dictionary);
+        &bytes[start..start + length]
+    }
+
+    #[test]
+    fn single_page() {
+        let image = RgbaImage::from_pixel(40, 20, Rgba([10, 20, 30, 255]));
+        let bytes = pdf(&[image], PageSize::Image);
+        assert!(bytes.starts_with(b"%PDF-1.4\n"));
+        assert!(bytes.ends_with(b"%%EOF\n"));
+
+        let objects = objects(&bytes);
+        assert_eq!(objects.len(), 5);
+        assert_eq!(objects[1], "<< /Type /Pages /Kids [3 0 R] /Count 1 >>");
+        assert!(objects[2].contains("/MediaBox [0 0 30.00 15.00]"), "{}", objects[2]);
+        assert_eq!(stream_data(&bytes, &objects[3]), b"q 30.00 0 0 15.00 0.00 0.00 cm /Im0 Do Q");
+
+        let mut pixels = vec![];
+        ZlibDecoder::new(stream_data(&bytes, &objects[4])).read_to_end(&mut pixels).unwrap();
+        assert_eq!(pixels.len(), 40 * 20 * 3);
+        assert_eq!(&pixels[..3], &[10, 20, 30]);
+    }
+
+    #[test]
+    fn one_page_per_image() {
+        let pages: Vec<RgbaImage> = (1..=3).map(|i| RgbaImage::new(10 * i, 8)).collect();
+        let bytes = pdf(&pages, PageSize::A4);
+        let objects = objects(&bytes);
+        assert_eq!(objects.len(), 2 + 3 * 3);
+        assert_eq!(objects[1], "<< /Type /Pages /Kids [3 0 R 6 0 R 9 0 R] /Count 3 >>");
+        for (i, image) in pages.iter().enumerate() {
+            let page = &objects[2 + 3 * i];
+            assert!(page.starts_with("<< /Type /Page /Parent 2 0 R"), "{}", page);
+            assert!(page.contains(&format!("/Im0 {} 0 R", 5 + 3 * i)), "{}", page);
+            assert!(page.contains(&format!("/Contents {} 0 R", 4 + 3 * i)), "{}", page);
+            assert!(objects[4 + 3 * i].contains(&format!("/Width {} /Height 8", image.width())), "{}", objects[4 + 3 * i]);
+        }
+    }
+
+    #[test]
+    fn stops_at_the_first_page_that_cannot_be_read() {
+        let pages = vec![Ok(RgbaImage::new(4, 4)), Err(YasaError::EmptyArea), Ok(RgbaImage::new(4, 4))];
+        assert!(matches!(write_pdf(vec![], pages, PageSize::Image), Err(YasaError::EmptyArea)));
+    }
+
+    #[test]
+    fn no_page() {
+        let objects = objects(&pdf(&[], PageSize::Image));
+        assert_eq!(objects[1], "<< /Type /Pages /Kids [] /Count 0 >>");
+    }
+
+    #[test]
+    fn a4_layout_fits_and_centres_the_image() {
+        // Small images keep their size.
+        let ((width, height), (x, y, image_width, image_height)) = layout(400, 200, PageSize::A4);
+        assert_eq!((width, height), (A4.1, A4.0));
+        assert_eq!((image_width, image_height), (300.0, 150.0));
+        assert_eq!((x, y), ((A4.1 - 300.0) / 2.0, (A4.0 - 150.0) / 2.0));
+
+        // Large ones are shrunk inside the margins, tall ones on a portrait page.
+        let ((width, height), (x, y, image_width, image_height)) = layout(1000, 4000, PageSize::A4);
+        assert_eq!((width, height), A4);
+        assert!((image_height - (A4.1 - 2.0 * A4_MARGIN)).abs() < 0.01);
+        assert!((image_width * 4.0 - image_height).abs() < 0.01);
+        assert!(x > A4_MARGIN && (y - A4_MARGIN).abs() < 0.01);
+    }
+}
@@ -0,0 +1,326 @@
+use chrono::Local;
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use zip::write::FileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+use super::error_utils::YasaError;
+use super::image_utils::{self, PixelArea};
+use super::painting_utils::{DrawObj, Overlay};
+use super::save_utils::{self, SavePath};
+
+pub const PROJECT_EXTENSION: &str = "yasa";
+/// Version of the project manifest. Bump it, and convert the older manifests in `read_manifest`, when its fields change.
+/// Version 2 added pasted images, each stored once as a PNG entry named after its id: version 1 manifests read as they are.
+pub const PROJECT_VERSION: u32 = 2;
+
+/// The original capture, without annotations.
+const IMAGE_ENTRY: &str = "capture.png";
+const MANIFEST_ENTRY: &str = "project.json";
+
+/// Entry holding the picture of a pasted image.
+fn overlay_entry(id: u64) -> String {
+    format!("overlays/{}.png", id)
+}
+
+/// A screenshot with its annotations and crop, still editable.
+pub struct Project {
+    pub image: RgbaImage,
+    /// Annotations, in pixels of `image`.
+    pub shapes: Vec<DrawObj>,
+    pub crop: Option<PixelArea>,
+}
+
+/// Everything but the image, stored as JSON next to it in the `.yasa` zip archive.
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    /// Version of YASA that wrote the project.
+    generator: String,
+    /// RFC 3339 date and time of the save.
+    saved: String,
+    width: u32,
+    height: u32,
+    crop: Option<PixelArea>,
+    shapes: Vec<DrawObj>,
+}
+
+/// Writes `project` to the folder and name in `save_path`, returning the full path written.
+pub fn save_project(save_path: &SavePath, project: &Project) -> Result<PathBuf, YasaError> {
+    let pathname = save_utils::target_path(save_path, PROJECT_EXTENSION)?;
+    write_project(File::create(&pathname)?, project)?;
+    Ok(pathname)
+}
+
+pub fn open_project(path: &Path) -> Result<Project, YasaError> {
+    read_project(File::open(path)?)
+}
+
+pub fn write_project<W: Write + Seek>(writer: W, project: &Project) -> Result<(), YasaError> {
+    let manifest = Manifest {
+        version: PROJECT_VERSION,
+        generator: format!("yasa {}", env!("CARGO_PKG_VERSION")),
+        saved: Local::now().to_rfc3339(),
+        width: project.image.width(),
+        height: project.image.height(),
+        crop: project.crop,
+        shapes: project.shapes.clone(),
+    };
+    let manifest = serde_json::to_vec_pretty(&manifest).map_err(|e| YasaError::Project(e.to_string()))?;
+    let png = image_utils::encode_png(&project.image)?;
+
+    let mut zip = ZipWriter::new(writer);
+    zip.start_file(MANIFEST_ENTRY, FileOptions::default())?;
+    zip.write_all(&manifest)?;
+    // PNGs are compressed already.
+    let stored = FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    zip.start_file(IMAGE_ENTRY, stored)?;
+    zip.write_all(&png)?;
+    // Copies of a pasted image share its id, the picture is stored once.
+    let mut written = vec![];
+    for overlay in project.shapes.iter().filter_map(DrawObj::overlay) {
+        if written.contains(&overlay.id()) {
+            continue;
+        }
+        written.push(overlay.id());
+        zip.start_file(overlay_entry(overlay.id()), stored)?;
+        zip.write_all(&image_utils::encode_png(overlay.image())?)?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+pub fn read_project<R: Read + Seek>(reader: R) -> Result<Project, YasaError> {
+    let mut zip = ZipArchive::new(reader)?;
+    let mut manifest = vec![];
+    zip.by_name(MANIFEST_ENTRY)?.read_to_end(&mut manifest)?;
+    let manifest = read_manifest(&manifest)?;
+    let image = read_png(&mut zip, IMAGE_ENTRY)?;
+
+    if image.dimensions() != (manifest.width, manifest.height) {
+        return Err(YasaError::Project(format!(
+            "the capture is {}×{}, the project expects {}×{}",
+            image.width(),
+            image.height(),
+            manifest.width,
+            manifest.height
+        )));
+    }
+    if let Some((x, y, width, height)) = manifest.crop {
+        let fits = |start: u32, length: u32, size: u32| length > 0 && start.checked_add(length).map_or(false, |end| end <= size);
+        let inside = fits(x, width, image.width()) && fits(y, height, image.height());
+        if !inside {
+            return Err(YasaError::Project("the crop is outside of the capture".to_string()));
+        }
+    }
+
+    for (i, shape) in manifest.shapes.iter().enumerate() {
+        shape.validate().map_err(|e| YasaError::Project(format!("shape {}: {}", i + 1, e)))?;
+    }
+
+    // Pasted images get new ids, so that they do not share textures with the ones already open.
+    let mut shapes = manifest.shapes;
+    let mut overlays: HashMap<u64, Overlay> = HashMap::new();
+    for overlay in shapes.iter_mut().filter_map(DrawObj::overlay_mut) {
+        let loaded = match overlays.get(&overlay.id()) {
+            Some(loaded) => loaded.clone(),
+            None => {
+                let loaded = Overlay::new(read_png(&mut zip, &overlay_entry(overlay.id()))?);
+                overlays.insert(overlay.id(), loaded.clone());
+                loaded
+            }
+        };
+        *overlay = loaded;
+    }
+    Ok(Project { image, shapes, crop: manifest.crop })
+}
+
+fn read_png<R: Read + Seek>(zip: &mut ZipArchive<R>, entry: &str) -> Result<RgbaImage, YasaError> {
+    let mut png = vec![];
+    zip.by_name(entry)?.read_to_end(&mut png)?;
+    Ok(image_utils::decode_png(&png)?)
+}
+
+/// Parses the manifest of any known version.
+fn read_manifest(bytes: &[u8]) -> Result<Manifest, YasaError> {
+    let value: serde_json::Value = serde_json::from_slice(bytes).map_err(|e| YasaError::Project(e.to_string()))?;
+    let version = value.get("version").and_then(serde_json::Value::as_u64).unwrap_or(0);
+    if version == 0 || version > PROJECT_VERSION as u64 {
+        return Err(YasaError::Project(format!("unsupported project version {}", version)));
+    }
+    serde_json::from_value(value).map_err(|e| YasaError::Project(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::painting_utils::Painting;
+    use image::Rgba;
+    use serde_json::json;
+    use std::io::Cursor;
+
+    fn capture() -> RgbaImage {
+        RgbaImage::from_fn(40, 30, |x, y| Rgba([x as u8 * 6, y as u8 * 8, 90, 255]))
+    }
+
+    /// Shapes of every kind that keeps data of its own: points, fill, text and redaction.
+    fn shapes() -> Vec<DrawObj> {
+        let stroke = json!({ "width": 3.0, "color": [200, 20, 20, 255] });
+        serde_json::from_value(json!([
+            { "points": [{ "x": 1.0, "y": 2.0 }, { "x": 10.5, "y": 12.0 }, { "x": 20.0, "y": 4.0 }], "stroke": stroke, "shape": "Line", "filled": false, "text": null, "redaction": null },
+            { "points": [{ "x": 3.0, "y": 3.0 }, { "x": 15.0, "y": 9.0 }], "stroke": stroke, "shape": "Rectangle", "filled": true, "text": null, "redaction": null },
+            { "points": [{ "x": 5.0, "y": 20.0 }], "stroke": stroke, "shape": "Text", "filled": false, "text": { "text": "note", "size": 12.0, "background": [255, 255, 255, 255] }, "redaction": null },
+            { "points": [{ "x": 30.0, "y": 0.0 }, { "x": 40.0, "y": 10.0 }], "stroke": stroke, "shape": "Redaction", "filled": false, "text": null, "redaction": ["Blur", 4] },
+        ]))
+        .unwrap()
+    }
+
+    /// Project with the shapes above, a pasted image and a copy of it, and a crop.
+    fn project() -> Project {
+        let mut painting = Painting::new(None, Some(capture()));
+        painting.paste_image(RgbaImage::from_pixel(6, 4, Rgba([0, 200, 0, 128])));
+        let mut project = painting.project(false);
+        let pasted = project.shapes[0].clone();
+        project.shapes.splice(0..0, shapes());
+        project.shapes.push(pasted);
+        project.crop = Some((2, 3, 30, 20));
+        project
+    }
+
+    fn write(project: &Project) -> Vec<u8> {
+        let mut bytes = Cursor::new(vec![]);
+        write_project(&mut bytes, project).unwrap();
+        bytes.into_inner()
+    }
+
+    /// Archive holding `manifest` and `image`, for projects that `write_project` would not write.
+    fn archive(manifest: serde_json::Value, image: &RgbaImage) -> Cursor<Vec<u8>> {
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        zip.start_file(MANIFEST_ENTRY, FileOptions::default()).unwrap();
+        zip.write_all(manifest.to_string().as_bytes()).unwrap();
+        zip.start_file(IMAGE_ENTRY, FileOptions::default()).unwrap();
+        zip.write_all(&image_utils::encode_png(image).unwrap()).unwrap();
+        Cursor::new(zip.finish().unwrap().into_inner())
+    }
+
+    fn manifest(version: u32, width: u32, height: u32, crop: Option<PixelArea>) -> serde_json::Value {
+        json!({ "version": version, "generator": "test", "saved": "", "width": width, "height": height, "crop": crop, "shapes": [] })
+    }
+
+    fn error(result: Result<Project, YasaError>) -> String {
+        match result {
+            Ok(_) => panic!("the project was read"),
+            Err(YasaError::Project(message)) => message,
+            Err(e) => panic!("unexpected error {}", e),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let project = project();
+        let read = read_project(Cursor::new(write(&project))).unwrap();
+
+        assert_eq!(read.image, project.image);
+        assert_eq!(read.crop, Some((2, 3, 30, 20)));
+        assert_eq!(read.shapes.len(), project.shapes.len());
+        for (read, written) in read.shapes.iter().zip(&project.shapes) {
+            match (read.overlay(), written.overlay()) {
+                (Some(read), Some(written)) => assert_eq!(read.image(), written.image()),
+                (None, None) => assert!(read == written),
+                _ => panic!("a pasted image became a {:?}", read.kind()),
+            }
+        }
+    }
+
+    #[test]
+    fn pasted_images_are_stored_once_next_to_the_manifest() {
+        let project = project();
+        let id = project.shapes.last().unwrap().overlay().unwrap().id();
+        let mut zip = ZipArchive::new(Cursor::new(write(&project))).unwrap();
+        let mut names: Vec<&str> = zip.file_names().collect();
+        names.sort();
+        assert_eq!(names, vec![IMAGE_ENTRY, overlay_entry(id).as_str(), MANIFEST_ENTRY]);
+
+        let mut manifest = String::new();
+        zip.by_name(MANIFEST_ENTRY).unwrap().read_to_string(&mut manifest).unwrap();
+        assert!(manifest.len() < 4096, "the manifest holds image data: {}", manifest);
+
+        // Both copies still share one picture, under a new id.
+        let read = read_project(Cursor::new(write(&project))).unwrap();
+        let overlays: Vec<&Overlay> = read.shapes.iter().filter_map(DrawObj::overlay).collect();
+        assert_eq!(overlays.len(), 2);
+        assert_eq!(overlays[0].id(), overlays[1].id());
+        assert_ne!(overlays[0].id(), id);
+    }
+
+    #[test]
+    fn missing_pasted_image_is_an_error() {
+        let mut manifest = manifest(PROJECT_VERSION, 40, 30, None);
+        manifest["shapes"] = json!([{
+            "points": [{ "x": 0.0, "y": 0.0 }, { "x": 4.0, "y": 4.0 }], "stroke": { "width": 1.0, "color": [0, 0, 0, 255] },
+            "shape": "Image", "filled": false, "text": null, "redaction": null, "overlay": { "id": 7 },
+        }]);
+        assert!(read_project(archive(manifest, &capture())).is_err());
+    }
+
+    #[test]
+    fn rejects_shapes_that_cannot_be_drawn() {
+        let stroke = json!({ "width": 1.0, "color": [0, 0, 0, 255] });
+        let shape = |kind: &str, points: serde_json::Value| {
+            json!({ "points": points, "stroke": stroke, "shape": kind, "filled": false, "text": null, "redaction": null })
+        };
+        let one_point = json!([{ "x": 1.0, "y": 1.0 }]);
+        let cases = [
+            (shape("Rectangle", one_point.clone()), "shape 2: the rectangle has 1 points, too few to be drawn"),
+            (shape("Ellipse", json!([])), "shape 2: the ellipse has 0 points, too few to be drawn"),
+            (shape("Arrow", one_point.clone()), "shape 2: the arrow has 1 points, too few to be drawn"),
+            (shape("StraightLine", one_point.clone()), "shape 2: the straight line has 1 points, too few to be drawn"),
+            (shape("Line", json!([])), "shape 2: the line has 0 points, too few to be drawn"),
+            (shape("Redaction", json!([{ "x": 1.0, "y": 1.0 }, { "x": 5.0, "y": 5.0 }])), "shape 2: the redaction misses its content"),
+            (shape("Text", one_point.clone()), "shape 2: the text misses its content"),
+            (shape("Select", one_point.clone()), "shape 2: a selection is not a shape"),
+            (shape("Line", json!([{ "x": 1.0, "y": 1e39 }])), "shape 2: the line has a point that is not a finite number"),
+        ];
+        for (invalid, expected) in cases {
+            let mut manifest = manifest(PROJECT_VERSION, 40, 30, None);
+            manifest["shapes"] = json!([shape("Line", one_point.clone()), invalid]);
+            assert_eq!(error(read_project(archive(manifest, &capture()))), expected);
+        }
+    }
+
+    #[test]
+    fn reads_version_1() {
+        let read = read_project(archive(manifest(1, 40, 30, Some((0, 0, 40, 30))), &capture())).unwrap();
+        assert_eq!(read.crop, Some((0, 0, 40, 30)));
+        assert!(read.shapes.is_empty());
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        let message = error(read_project(archive(manifest(0, 40, 30, None), &capture())));
+        assert_eq!(message, "unsupported project version 0");
+        let message = error(read_project(archive(manifest(PROJECT_VERSION + 1, 40, 30, None), &capture())));
+        assert_eq!(message, format!("unsupported project version {}", PROJECT_VERSION + 1));
+        let mut no_version = manifest(1, 40, 30, None);
+        no_version.as_object_mut().unwrap().remove("version");
+        assert_eq!(error(read_project(archive(no_version, &capture()))), "unsupported project version 0");
+    }
+
+    #[test]
+    fn rejects_a_capture_of_another_size() {
+        let message = error(read_project(archive(manifest(PROJECT_VERSION, 41, 30, None), &capture())));
+        assert_eq!(message, "the capture is 40×30, the project expects 41×30");
+    }
+
+    #[test]
+    fn rejects_a_crop_outside_of_the_capture() {
+        for crop in [(30, 0, 11, 10), (0, 25, 10, 6), (0, 0, 0, 10), (u32::MAX, 0, 2, 2)] {
+            let message = error(read_project(archive(manifest(PROJECT_VERSION, 40, 30, Some(crop)), &capture())));
+            assert_eq!(message, "the crop is outside of the capture", "crop {:?}", crop);
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use egui::{Color32, Pos2, Vec2};
+use image::RgbaImage;
+
+/// Geometry of an annotation in pixels of the screenshot. The canvas preview and the
+/// exported image are both built from these, so that they always agree.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Primitive {
+    /// Polyline of the given width with round caps and joins. Closed when its last point is its first one.
+    Stroke(Vec<Pos2>, f32),
+    /// Filled convex polygon.
+    Fill(Vec<Pos2>),
+}
+
+impl Primitive {
+    /// Distance from `point` to the painted area, zero inside it. Used to pick shapes with the mouse.
+    pub fn distance(&self, point: Pos2) -> f32 {
+        match self {
+            Primitive::Stroke(points, width) => {
+                let to_line = match points.as_slice() {
+                    [] => f32::INFINITY,
+                    [single] => point.distance(*single),
+                    _ => points
+                        .windows(2)
+                        .map(|pair| distance_to_segment(point, pair[0], pair[1]))
+                        .fold(f32::INFINITY, f32::min),
+                };
+                (to_line - width / 2.0).max(0.0)
+            }
+            Primitive::Fill(polygon) => convex_edges(polygon)
+                .iter()
+                .map(|(corner, normal)| (point - *corner).dot(*normal))
+                .fold(f32::NEG_INFINITY, f32::max)
+                .max(0.0),
+        }
+    }
+}
+
+/// Coverage of a stroke over the pixels of an image, between 0 and 1.
+/// Overlapping parts of the stroke keep the highest coverage instead of adding up,
+/// so that translucent strokes are blended only once when the mask is composited.
+pub struct StrokeMask {
+    width: u32,
+    height: u32,
+    coverage: Vec<f32>,
+    /// Pixels touched since the last `clear`, as (x_min, y_min, x_max, y_max), max excluded.
+    dirty: Option<(u32, u32, u32, u32)>,
+}
+
+impl StrokeMask {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            coverage: vec![0.0; (width * height) as usize],
+            dirty: None,
+        }
+    }
+
+    pub fn add(&mut self, primitive: &Primitive) {
+        match primitive {
+            Primitive::Stroke(points, width) => self.stroke_polyline(points, *width),
+            Primitive::Fill(polygon) => self.fill_convex_polygon(polygon),
+        }
+    }
+
+    /// Resets the mask, so that it can be reused for the next shape.
+    pub fn clear(&mut self) {
+        if let Some((x_min, y_min, x_max, y_max)) = self.dirty.take() {
+            for y in y_min..y_max {
+                let row = (y * self.width) as usize;
+                self.coverage[row + x_min as usize..row + x_max as usize].fill(0.0);
+            }
+        }
+    }
+
+    /// Sets the coverage of the pixels in the box around `min`..`max` (grown by one pixel for anti-aliasing)
+    /// to `coverage(pixel centre)` where that is larger than the current one.
+    fn cover(&mut self, min: Pos2, max: Pos2, coverage: impl Fn(Pos2) -> f32) {
+        let x_min = (min.x - 1.0).floor().max(0.0) as u32;
+        let y_min = (min.y - 1.0).floor().max(0.0) as u32;
+        let x_max = ((max.x + 1.0).ceil().max(0.0) as u32).min(self.width);
+        let y_max = ((max.y + 1.0).ceil().max(0.0) as u32).min(self.height);
+        if x_min >= x_max || y_min >= y_max {
+            return;
+        }
+
+        for y in y_min..y_max {
+            for x in x_min..x_max {
+                let value = coverage(Pos2::new(x as f32 + 0.5, y as f32 + 0.5)).clamp(0.0, 1.0);
+                let index = (y * self.width + x) as usize;
+                self.coverage[index] = self.coverage[index].max(value);
+            }
+        }
+        self.dirty = Some(match self.dirty {
+            Some((a, b, c, d)) => (a.min(x_min), b.min(y_min), c.max(x_max), d.max(y_max)),
+            None => (x_min, y_min, x_max, y_max),
+        });
+    }
+
+    /// Sets the coverage of the pixel at (`x`, `y`), if it is inside the mask, where that is larger than the current one.
+    /// For shapes rasterised elsewhere, such as the glyphs of a text.
+    pub fn cover_pixel(&mut self, x: i32, y: i32, coverage: f32) {
+        if x < 0 || y < 0 || x as u32 >= self.width || y as u32 >= self.height {
+            return;
+        }
+        let (x, y) = (x as u32, y as u32);
+        let index = (y * self.width + x) as usize;
+        self.coverage[index] = self.coverage[index].max(coverage.clamp(0.0, 1.0));
+        self.dirty = Some(match self.dirty {
+            Some((a, b, c, d)) => (a.min(x), b.min(y), c.max(x + 1), d.max(y + 1)),
+            None => (x, y, x + 1, y + 1),
+        });
+    }
+
+    /// Adds a segment of the given width with round ends. Edges are anti-aliased over one pixel.
+    pub fn stroke_segment(&mut self, a: Pos2, b: Pos2, stroke_width: f32) {
+        let reach = Vec2::splat(stroke_width / 2.0);
+        self.cover(a.min(b) - reach, a.max(b) + reach, |center| {
+            stroke_width / 2.0 + 0.5 - distance_to_segment(center, a, b)
+        });
+    }
+
+    /// Adds a convex polygon, given in either winding order.
+    pub fn fill_convex_polygon(&mut self, polygon: &[Pos2]) {
+        if polygon.len() < 3 {
+            return;
+        }
+        let edges = convex_edges(polygon);
+        let min = polygon.iter().fold(Pos2::new(f32::INFINITY, f32::INFINITY), |acc, p| acc.min(*p));
+        let max = polygon.iter().fold(Pos2::new(f32::NEG_INFINITY, f32::NEG_INFINITY), |acc, p| acc.max(*p));
+        self.cover(min, max, |center| {
+            let distance = edges
+                .iter()
+                .map(|(point, normal)| (center - *point).dot(*normal))
+                .fold(f32::NEG_INFINITY, f32::max);
+            0.5 - distance
+        });
+    }
+
+    /// Adds a polyline; the round ends of its segments also give it round joins.
+    pub fn stroke_polyline(&mut self, points: &[Pos2], stroke_width: f32) {
+        match points {
+            [] => {}
+            [point] => self.stroke_segment(*point, *point, stroke_width),
+            _ => {
+                for pair in points.windows(2) {
+                    self.stroke_segment(pair[0], pair[1], stroke_width);
+                }
+            }
+        }
+    }
+
+    /// Blends `color` (premultiplied, as stored by egui) over `image` where the mask is set.
+    pub fn composite(&self, image: &mut RgbaImage, color: Color32) {
+        let Some((x_min, y_min, x_max, y_max)) = self.dirty else {
+            return;
+        };
+        let [src_r, src_g, src_b, src_a] = color.to_array().map(|channel| channel as f32 / 255.0);
+        for (x, y) in (y_min..y_max).flat_map(|y| (x_min..x_max).map(move |x| (x, y))) {
+            let coverage = &self.coverage[(y * self.width + x) as usize];
+            if *coverage <= 0.0 {
+                continue;
+            }
+            let pixel = image.get_pixel_mut(x, y);
+            let [dst_r, dst_g, dst_b, dst_a] = pixel.0.map(|channel| channel as f32 / 255.0);
+
+            let alpha = src_a * coverage;
+            let out_a = alpha + dst_a * (1.0 - alpha);
+            if out_a <= 0.0 {
+                continue;
+            }
+            let blend = |src: f32, dst: f32| (src * coverage + dst * dst_a * (1.0 - alpha)) / out_a;
+            pixel.0 = [blend(src_r, dst_r), blend(src_g, dst_g), blend(src_b, dst_b), out_a]
+                .map(|channel| (channel * 255.0).round().clamp(0.0, 255.0) as u8);
+        }
+    }
+}
+
+fn distance_to_segment(point: Pos2, a: Pos2, b: Pos2) -> f32 {
+    let segment = b - a;
+    let length_sq = segment.length_sq();
+    if length_sq <= 0.0 {
+        return point.distance(a);
+    }
+    let t = ((point - a).dot(segment) / length_sq).clamp(0.0, 1.0);
+    point.distance(a + segment * t)
+}
+
+/// A point and the outward normal of every edge of a convex polygon.
+fn convex_edges(polygon: &[Pos2]) -> Vec<(Pos2, Vec2)> {
+    let centroid = polygon.iter().fold(Pos2::ZERO, |acc, p| acc + p.to_vec2() / polygon.len() as f32);
+    polygon
+        .iter()
+        .zip(polygon.iter().cycle().skip(1))
+        .filter(|(a, b)| a != b)
+        .map(|(a, b)| {
+            let normal = (*b - *a).normalized().rot90();
+            (*a, if (centroid - *a).dot(normal) > 0.0 { -normal } else { normal })
+        })
+        .collect()
+}
@@ -1,60 +1,143 @@
-use image::ImageBuffer;
+use super::error_utils::YasaError;
+use super::painting_utils::Painting;
+use super::pdf_utils::{self, PageSize};
+use super::project_utils;
+use super::svg_utils;
+use image::{DynamicImage, ImageBuffer, ImageFormat};
 use crate::app::ImgFormats;
-use std::path::PathBuf;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
 use chrono::{DateTime, Local};
 
+/// Default `name_template`: the capture date and time, without characters forbidden in file names.
+pub const DEFAULT_NAME_TEMPLATE: &str = "%Y-%m-%dT_%H_%M_%S";
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SavePath{
     pub path: PathBuf,
     pub name: String,
     pub format: ImgFormats,
     pub user_mod_name: bool,
+    /// `chrono` format string used to name new captures.
+    pub name_template: String,
+    /// Page of each image in a PDF.
+    pub page_size: PageSize,
+    /// Writes the capture of a project with its redactions applied, so that the hidden pixels cannot be recovered from it.
+    pub bake_redactions: bool,
 }
 
 impl SavePath {
     pub fn new(path: PathBuf, format: ImgFormats) -> Self {
-        let date: DateTime<Local> = Local::now();
-        let formatted = date.format("%Y-%m-%dT%H:%M:%S");
-        let name = formatted.to_string();
         Self {
             path,
             format,
-            name,
+            name: generate_filename(DEFAULT_NAME_TEMPLATE),
             user_mod_name: false,
+            name_template: DEFAULT_NAME_TEMPLATE.to_string(),
+            page_size: PageSize::default(),
+            bake_redactions: true,
         }
     }
 
 }
 
-pub fn save_image(save_path: &SavePath, picture: ImageBuffer<image::Rgba<u8>, Vec<u8>>){
-    let mut pathname = save_path.path.to_string_lossy().to_string().to_owned();
-    pathname.push_str("/");
-    let filename = save_path.name.to_owned();
-    pathname.push_str(&filename);
-    if save_path.format == ImgFormats::PNG{
-        pathname.push_str(".png");
+/// Writes the edited screenshot to the folder, name and format in `save_path`, returning the full path written.
+pub fn save(save_path: &SavePath, painting: &mut Painting) -> Result<PathBuf, YasaError> {
+    match save_path.format {
+        ImgFormats::YASA => project_utils::save_project(save_path, &painting.project(save_path.bake_redactions)),
+        ImgFormats::SVG => svg_utils::save_svg(save_path, &painting.svg()?),
+        ImgFormats::PDF => pdf_utils::save_pdf(save_path, [Ok(painting.generate_rgba_image())]),
+        ImgFormats::PNG | ImgFormats::JPEG | ImgFormats::GIF => save_image(save_path, painting.generate_rgba_image()),
     }
-    else if save_path.format == ImgFormats::JPEG{
-        pathname.push_str(".jpeg");
+}
+
+/// Writes `picture` to the folder, name and format in `save_path`, returning the full path written.
+/// Only PNG, JPEG and GIF hold a flat image, the other formats are written by `save`.
+pub fn save_image(save_path: &SavePath, picture: ImageBuffer<image::Rgba<u8>, Vec<u8>>) -> Result<PathBuf, YasaError> {
+    let (_, extension) = image_format(save_path.format)?;
+    let pathname = target_path(save_path, extension)?;
+    write_image(&pathname, save_path.format, picture)?;
+    Ok(pathname)
+}
+
+/// Writes `picture` to exactly `pathname`, in `format` whatever its extension.
+pub fn write_image(pathname: &Path, format: ImgFormats, picture: ImageBuffer<image::Rgba<u8>, Vec<u8>>) -> Result<(), YasaError> {
+    let (image_format, _) = image_format(format)?;
+    if format == ImgFormats::JPEG {
+        // JPEG has no alpha channel
+        DynamicImage::ImageRgba8(picture).to_rgb8().save_with_format(pathname, image_format)?;
+    } else {
+        picture.save_with_format(pathname, image_format)?;
     }
-    else if save_path.format == ImgFormats::GIF{
-        pathname.push_str(".gif");
+    Ok(())
+}
+
+/// Encoder and file extension of the flat image formats.
+fn image_format(format: ImgFormats) -> Result<(ImageFormat, &'static str), YasaError> {
+    match format {
+        ImgFormats::PNG => Ok((ImageFormat::Png, "png")),
+        ImgFormats::JPEG => Ok((ImageFormat::Jpeg, "jpeg")),
+        ImgFormats::GIF => Ok((ImageFormat::Gif, "gif")),
+        ImgFormats::YASA | ImgFormats::SVG | ImgFormats::PDF => Err(YasaError::NotAnImageFormat(format)),
     }
+}
 
-    image::save_buffer(pathname,  &picture.as_raw().as_slice(), picture.width(), picture.height(), image::ColorType::Rgba8).unwrap();
+/// Full path of the file to write for `save_path`, after checking its folder and name.
+pub fn target_path(save_path: &SavePath, extension: &str) -> Result<PathBuf, YasaError> {
+    if save_path.name.is_empty() || !check_filename(&save_path.name) {
+        return Err(YasaError::InvalidFilename(save_path.name.clone()));
+    }
+    if !save_path.path.is_dir() {
+        return Err(YasaError::InvalidPath(save_path.path.clone()));
+    }
+    Ok(save_path.path.join(format!("{}.{}", save_path.name, extension)))
 }
 
-pub fn generate_filename() -> String {
+/// Names a new capture after `template`, falling back to the default one if the template is not valid.
+pub fn generate_filename(template: &str) -> String {
     let date: DateTime<Local> = Local::now();
-    let formatted = date.format("%Y-%m-%dT_%H_%M_%S");
-    formatted.to_string()
+    let mut formatted = String::new();
+    if write!(formatted, "{}", date.format(template)).is_err() || formatted.is_empty() || !check_filename(&formatted) {
+        return date.format(DEFAULT_NAME_TEMPLATE).to_string();
+    }
+    formatted
 }
 
 pub fn check_filename(name: &str) -> bool {
-    if name.contains("\\") || name.contains("/") || name.contains(":") || name.contains("*") || name.contains("?") || name.contains("\"") || name.contains("<") || name.contains(">") || name.contains("|") {
-        return false;
+    !name.contains(['\\', '/', ':', '*', '?', '"', '<', '>', '|'])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbaImage;
+
+    fn temp_folder(name: &str) -> PathBuf {
+        let folder = std::env::temp_dir().join(format!("yasa-save-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&folder).unwrap();
+        folder
     }
-    else {
-        return true;
+
+    #[test]
+    fn save_image_rejects_formats_that_are_not_images() {
+        let folder = temp_folder("formats");
+        for format in [ImgFormats::YASA, ImgFormats::SVG, ImgFormats::PDF] {
+            let save_path = SavePath::new(folder.clone(), format);
+            let result = save_image(&save_path, RgbaImage::new(2, 2));
+            assert!(matches!(result, Err(YasaError::NotAnImageFormat(f)) if f == format));
+        }
+        assert_eq!(std::fs::read_dir(&folder).unwrap().count(), 0);
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn save_image_writes_the_format_extension() {
+        let folder = temp_folder("extension");
+        let mut save_path = SavePath::new(folder.clone(), ImgFormats::JPEG);
+        save_path.name = "shot".to_string();
+        let pathname = save_image(&save_path, RgbaImage::new(4, 3)).unwrap();
+        assert_eq!(pathname, folder.join("shot.jpeg"));
+        assert_eq!(image::open(&pathname).unwrap().width(), 4);
+        std::fs::remove_dir_all(folder).unwrap();
     }
-}
\ No newline at end of file
+}
@@ -1,100 +1,289 @@
-use super::screenshot_view::ScreenshotView;
-use crate::app::ScreenshotType;
+use super::backend_utils::{self, CaptureBackend, DisplayGeometry};
+use super::error_utils::YasaError;
 use image::{GenericImage, RgbaImage};
-use screenshots::Screen;
-use std::io::Cursor;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Extra wait after the countdown, leaving the window time to disappear before the capture.
+const HIDE_DELAY: Duration = Duration::from_millis(150);
+
+/// What to capture. Coordinates are physical pixels relative to the top left corner of the first display.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CaptureArea {
+    AllScreens,
+    Screen(u32),
+    Region { x: i32, y: i32, width: u32, height: u32 },
+}
+
+/// A capture running on a background thread once its countdown expires.
+/// The result is delivered through a channel, so the UI never blocks while waiting.
+pub struct ScheduledCapture {
+    deadline: Instant,
+    cancelled: Arc<AtomicBool>,
+    rx_screenshot_buffer: Receiver<Result<RgbaImage, YasaError>>,
+}
+
+impl ScheduledCapture {
+    pub fn start(area: Option<CaptureArea>, delay: Duration, ctx: egui::Context) -> Self {
+        let deadline = Instant::now() + delay;
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let (tx_screenshot_buffer, rx_screenshot_buffer) = mpsc::channel();
+
+        let thread_cancelled = cancelled.clone();
+        thread::spawn(move || {
+            let capture_time = deadline + HIDE_DELAY;
+            while let Some(wait) = capture_time.checked_duration_since(Instant::now()) {
+                if thread_cancelled.load(Ordering::Relaxed) {
+                    return;
+                }
+                thread::sleep(wait.min(Duration::from_millis(50)));
+            }
+            if thread_cancelled.load(Ordering::Relaxed) {
+                return;
+            }
+            let backend = backend_utils::default_backend();
+            let screenshot_image_buffer = area
+                .ok_or(YasaError::EmptyArea)
+                .and_then(|area| take_screenshot(backend.as_ref(), area));
+            if tx_screenshot_buffer.send(screenshot_image_buffer).is_ok() {
+                ctx.request_repaint();
+            }
+        });
+
+        Self {
+            deadline,
+            cancelled,
+            rx_screenshot_buffer,
+        }
+    }
+
+    /// Time left before the window has to be hidden for the capture.
+    pub fn remaining(&self) -> Duration {
+        self.deadline.saturating_duration_since(Instant::now())
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// The capture result, once available.
+    pub fn try_result(&self) -> Option<Result<RgbaImage, YasaError>> {
+        match self.rx_screenshot_buffer.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(YasaError::Capture("the capture thread stopped unexpectedly".to_string()))),
+        }
+    }
+}
 
 struct ScreenImage {
-    screen: Screen,
-    image: screenshots::Image,
+    display: DisplayGeometry,
+    image: RgbaImage,
 }
 
-pub fn take_screenshot(
-    _screenshot_type: Option<ScreenshotType>,
-    _grabbed_area: Option<ScreenshotView>,
-    _ctx: &egui::Context,
-) -> Option<image::RgbaImage> {
-    let mut img: RgbaImage;
-    let screen_images = Screen::all()
-        .unwrap()
+pub fn take_screenshot(backend: &dyn CaptureBackend, area: CaptureArea) -> Result<RgbaImage, YasaError> {
+    match area {
+        CaptureArea::AllScreens => capture_all_screens(backend).map(|(img, _)| img),
+        CaptureArea::Screen(display_id) => capture_single_screen(backend, display_id),
+        CaptureArea::Region { x, y, width, height } => {
+            let (img, offset) = capture_all_screens(backend)?;
+            crop_region(&img, x - offset.0, y - offset.1, width, height)
+        }
+    }
+}
+
+/// Composites every display into one canvas, returning it with the position of its top left corner.
+fn capture_all_screens(backend: &dyn CaptureBackend) -> Result<(RgbaImage, (i32, i32)), YasaError> {
+    let screen_images = backend
+        .displays()?
         .into_iter()
-        .map(|screen| {
-            let image = screen.capture().unwrap();
-            ScreenImage { screen, image }
+        .map(|display| {
+            let image = backend.capture(&display)?;
+            Ok(ScreenImage { display, image })
         })
-        .collect::<Vec<ScreenImage>>();
-    let x_min = screen_images
-        .iter()
-        .map(|s| s.screen.display_info.x * s.screen.display_info.scale_factor as i32)
-        .min()
-        .unwrap();
-    let y_min = screen_images
-        .iter()
-        .map(|s| s.screen.display_info.y * s.screen.display_info.scale_factor as i32)
-        .min()
-        .unwrap();
+        .collect::<Result<Vec<ScreenImage>, YasaError>>()?;
+    if screen_images.is_empty() {
+        return Err(YasaError::NoDisplays);
+    }
+    composite(screen_images)
+}
+
+/// Each display is placed at its position scaled by its own scale factor (e.g. 1.5), with its captured image at native size.
+fn composite(screen_images: Vec<ScreenImage>) -> Result<(RgbaImage, (i32, i32)), YasaError> {
+    let physical_x = |d: &DisplayGeometry| (d.x as f32 * d.scale_factor).round() as i32;
+    let physical_y = |d: &DisplayGeometry| (d.y as f32 * d.scale_factor).round() as i32;
+    let x_min = screen_images.iter().map(|s| physical_x(&s.display)).min().unwrap_or(0);
+    let y_min = screen_images.iter().map(|s| physical_y(&s.display)).min().unwrap_or(0);
     let x_max = screen_images
         .iter()
-        .map(|s| (s.screen.display_info.x + s.screen.display_info.width as i32) * s.screen.display_info.scale_factor as i32)
+        .map(|s| physical_x(&s.display) + s.image.width() as i32)
         .max()
-        .unwrap();
+        .unwrap_or(0);
     let y_max = screen_images
         .iter()
-        .map(|s| (s.screen.display_info.y + s.screen.display_info.height as i32) * s.screen.display_info.scale_factor as i32)
+        .map(|s| physical_y(&s.display) + s.image.height() as i32)
         .max()
-        .unwrap();
+        .unwrap_or(0);
 
     let offset = (x_min, y_min);
-    let size: (u32, u32);
-    size = ((x_max - x_min) as u32, (y_max - y_min) as u32);
-    img = RgbaImage::new(size.0, size.1);
-    for pixels in img.enumerate_pixels_mut() {
-        *pixels.2 = image::Rgba([0, 0, 0, 255]);
-    }
+    let size = ((x_max - x_min) as u32, (y_max - y_min) as u32);
+    let mut img = RgbaImage::from_pixel(size.0, size.1, image::Rgba([0, 0, 0, 255]));
     for screen_image in screen_images {
-            let screenshot = image::io::Reader::new(Cursor::new(screen_image.image.to_png().unwrap()))
-                .with_guessed_format()
-                .unwrap()
-                .decode()
-                .unwrap();
-
-            let x = (screen_image.screen.display_info.x * screen_image.screen.display_info.scale_factor as i32 - offset.0) as u32;
-            let y = (screen_image.screen.display_info.y * screen_image.screen.display_info.scale_factor as i32 - offset.1) as u32;
-            if x + screenshot.width() <= img.width() && y + screenshot.height() <= img.height() {
-                    match img.copy_from(&screenshot, x, y) {
-                        Ok(_) => (),
-                        Err(e) => println!("Failed to copy screen image: {}", e),
-                    }
-            }   
-    }
-    if _screenshot_type.clone().unwrap() == ScreenshotType::PartialScreen{
-        let grab = _grabbed_area.clone().unwrap();
-        let x_start: i32;
-        let y_start: i32;
-        if grab.starting_point.x > grab.ending_point.x {
-            x_start = (grab.ending_point.x * _ctx.pixels_per_point()) as i32 as i32 - offset.0 as i32;
-        } else {
-            x_start = (grab.starting_point.x * _ctx.pixels_per_point()) as i32 as i32 - offset.0 as i32;
-        }
-        if grab.starting_point.y < grab.ending_point.y {
-            y_start = (grab.starting_point.y * _ctx.pixels_per_point()) as i32 as i32 - offset.1 as i32;
-        } else {
-            y_start = (grab.ending_point.y * _ctx.pixels_per_point()) as i32 as i32 - offset.1 as i32;
-        }
-        let clone = img.clone();
-        let (real_x, real_y) = (grab.dimension_selected.x * _ctx.pixels_per_point(), grab.dimension_selected.y * _ctx.pixels_per_point());
-        let cropped = image::imageops::crop_imm(&clone, x_start as u32, y_start as u32, real_x as u32, real_y as u32);
-        let cropped_to_img = cropped.to_image();
-        img = RgbaImage::new(real_x as u32, real_y as u32);
-        match img.copy_from(&cropped_to_img, 0, 0) {
-            Ok(_) => (),
-            Err(e) => println!("Failed to copy screen image: {}", e),
-        }
+        let screenshot = screen_image.image;
+        let x = (physical_x(&screen_image.display) - offset.0) as u32;
+        let y = (physical_y(&screen_image.display) - offset.1) as u32;
+        img.copy_from(&screenshot, x, y)?;
+    }
+    Ok((img, offset))
+}
+
+/// Captures only the display with the given id, at its native resolution.
+fn capture_single_screen(backend: &dyn CaptureBackend, display_id: u32) -> Result<RgbaImage, YasaError> {
+    let display = backend
+        .displays()?
+        .into_iter()
+        .find(|d| d.id == display_id)
+        .ok_or(YasaError::DisplayNotFound(display_id))?;
+    backend.capture(&display)
+}
+
+/// Crops `img` to the given rectangle, clamped to the image bounds.
+fn crop_region(img: &RgbaImage, x: i32, y: i32, width: u32, height: u32) -> Result<RgbaImage, YasaError> {
+    let x_start = x.clamp(0, img.width() as i32);
+    let y_start = y.clamp(0, img.height() as i32);
+    let x_end = x.saturating_add(width as i32).clamp(0, img.width() as i32);
+    let y_end = y.saturating_add(height as i32).clamp(0, img.height() as i32);
+    if x_end <= x_start || y_end <= y_start {
+        return Err(YasaError::EmptyArea);
+    }
+    Ok(
+        image::imageops::crop_imm(img, x_start as u32, y_start as u32, (x_end - x_start) as u32, (y_end - y_start) as u32)
+            .to_image(),
+    )
+}
+
+/// Display ids in enumeration order, as used by `--monitor N` on the command line.
+pub fn display_ids(backend: &dyn CaptureBackend) -> Result<Vec<u32>, YasaError> {
+    Ok(backend.displays()?.iter().map(|d| d.id).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use backend_utils::FakeBackend;
+    use image::Rgba;
+
+    fn display(id: u32, x: i32, y: i32, width: u32, height: u32, scale_factor: f32) -> DisplayGeometry {
+        DisplayGeometry { id, x, y, width, height, scale_factor, is_primary: id == 1 }
+    }
+
+    fn filled(width: u32, height: u32, value: u8) -> RgbaImage {
+        RgbaImage::from_pixel(width, height, Rgba([value, value, value, 255]))
+    }
+
+    #[test]
+    fn composite_places_displays_side_by_side() {
+        let (image, offset) = composite(vec![
+            ScreenImage { display: display(1, 0, 0, 4, 2, 1.0), image: filled(4, 2, 10) },
+            ScreenImage { display: display(2, 4, 0, 2, 3, 1.0), image: filled(2, 3, 20) },
+        ])
+        .unwrap();
+        assert_eq!(offset, (0, 0));
+        assert_eq!(image.dimensions(), (6, 3));
+        assert_eq!(image.get_pixel(3, 1).0[0], 10);
+        assert_eq!(image.get_pixel(4, 2).0[0], 20);
+        // Not covered by any display.
+        assert_eq!(image.get_pixel(0, 2).0, [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn composite_starts_at_the_leftmost_display() {
+        let (image, offset) = composite(vec![
+            ScreenImage { display: display(1, 0, 0, 4, 4, 1.0), image: filled(4, 4, 10) },
+            ScreenImage { display: display(2, -2, -1, 2, 2, 1.0), image: filled(2, 2, 20) },
+        ])
+        .unwrap();
+        assert_eq!(offset, (-2, -1));
+        assert_eq!(image.dimensions(), (6, 5));
+        assert_eq!(image.get_pixel(0, 0).0[0], 20);
+        assert_eq!(image.get_pixel(2, 1).0[0], 10);
+    }
+
+    #[test]
+    fn composite_mixes_scale_factors() {
+        // The second display is 4x2 logical points at 1.5: its position and image are in physical pixels.
+        let (image, offset) = composite(vec![
+            ScreenImage { display: display(1, 0, 0, 4, 2, 1.0), image: filled(4, 2, 10) },
+            ScreenImage { display: display(2, 4, 0, 4, 2, 1.5), image: filled(6, 3, 20) },
+        ])
+        .unwrap();
+        assert_eq!(offset, (0, 0));
+        assert_eq!(image.dimensions(), (12, 3));
+        assert_eq!(image.get_pixel(3, 0).0[0], 10);
+        assert_eq!(image.get_pixel(5, 0).0, [0, 0, 0, 255]);
+        assert_eq!(image.get_pixel(6, 0).0[0], 20);
+        assert_eq!(image.get_pixel(11, 2).0[0], 20);
+    }
+
+    #[test]
+    fn composite_of_a_retina_display_keeps_its_native_size() {
+        let (image, _) = composite(vec![ScreenImage { display: display(1, 0, 0, 3, 2, 2.0), image: filled(6, 4, 30) }]).unwrap();
+        assert_eq!(image.dimensions(), (6, 4));
+        assert_eq!(image.get_pixel(5, 3).0[0], 30);
+    }
+
+    #[test]
+    fn crop_region_clamps_to_the_image() {
+        let image = RgbaImage::from_fn(10, 8, |x, y| Rgba([x as u8, y as u8, 0, 255]));
+        let cropped = crop_region(&image, 2, 3, 4, 2).unwrap();
+        assert_eq!(cropped.dimensions(), (4, 2));
+        assert_eq!(cropped.get_pixel(0, 0).0, [2, 3, 0, 255]);
+
+        let clamped = crop_region(&image, -3, 6, 6, 10).unwrap();
+        assert_eq!(clamped.dimensions(), (3, 2));
+        assert_eq!(clamped.get_pixel(0, 0).0, [0, 6, 0, 255]);
     }
-    let return_img: Option<RgbaImage> = Some(img);
-    if return_img.is_some() {
-        return return_img;
-    } else {
-        return None;
+
+    #[test]
+    fn crop_region_outside_of_the_image_is_an_empty_area() {
+        let image = filled(10, 8, 0);
+        assert!(matches!(crop_region(&image, 10, 0, 5, 5), Err(YasaError::EmptyArea)));
+        assert!(matches!(crop_region(&image, -5, 0, 5, 5), Err(YasaError::EmptyArea)));
+        assert!(matches!(crop_region(&image, 0, 0, 0, 5), Err(YasaError::EmptyArea)));
+    }
+
+    #[test]
+    fn capture_single_screen_returns_that_display_only() {
+        let backend = FakeBackend::sample();
+        let image = capture_single_screen(&backend, 2).unwrap();
+        assert_eq!(image.dimensions(), (320, 240));
+        assert_eq!(image.get_pixel(0, 0).0[2], 255);
+        assert!(matches!(capture_single_screen(&backend, 7), Err(YasaError::DisplayNotFound(7))));
+    }
+
+    #[test]
+    fn take_screenshot_composites_and_crops_through_the_backend() {
+        let backend = FakeBackend::sample();
+        let all = take_screenshot(&backend, CaptureArea::AllScreens).unwrap();
+        assert_eq!(all.dimensions(), (960, 480));
+
+        // A region across both displays.
+        let region = take_screenshot(&backend, CaptureArea::Region { x: 600, y: 10, width: 100, height: 20 }).unwrap();
+        assert_eq!(region.dimensions(), (100, 20));
+        assert_eq!(region.get_pixel(0, 0).0[2], 0);
+        assert_eq!(region.get_pixel(99, 0).0[2], 255);
+
+        let outside = take_screenshot(&backend, CaptureArea::Region { x: 2000, y: 0, width: 10, height: 10 });
+        assert!(matches!(outside, Err(YasaError::EmptyArea)));
+    }
+
+    #[test]
+    fn take_screenshot_without_displays_fails() {
+        let backend = FakeBackend::new(vec![]);
+        assert!(matches!(take_screenshot(&backend, CaptureArea::AllScreens), Err(YasaError::NoDisplays)));
+        assert!(matches!(take_screenshot(&backend, CaptureArea::Screen(1)), Err(YasaError::DisplayNotFound(1))));
     }
 }
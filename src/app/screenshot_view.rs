@@ -1,6 +1,8 @@
 use super::ScreenshotType;
+use super::screenshot_utils::CaptureArea;
+use super::backend_utils::{self, DisplayGeometry};
+use super::window_utils::{self, WindowInfo};
 use crate::app;
-use display_info::DisplayInfo;
 use egui::*;
 
 #[derive(Clone)]
@@ -13,6 +15,11 @@ pub struct ScreenshotView {
     pub dimension_selected: Vec2,
     pub finished_selection: bool,
     pub screen_selected: u32,
+    pub monitor_choice: Option<u32>,
+    displays: Vec<DisplayGeometry>,
+    pub window_picking: bool,
+    windows: Vec<WindowInfo>,
+    pub selected_window: Option<WindowInfo>,
     pub timer_delay: i32,
 }
 
@@ -27,6 +34,11 @@ impl Default for ScreenshotView {
             dimension_selected: Default::default(),
             finished_selection: false,
             screen_selected: 0,
+            monitor_choice: None,
+            displays: backend_utils::default_backend().displays().unwrap_or_default(),
+            window_picking: false,
+            windows: vec![],
+            selected_window: None,
             timer_delay: 0,
         }
     }
@@ -47,10 +59,6 @@ impl ScreenshotView {
         let width = _frame.info().window_info.monitor_size.unwrap().x;
         let height = _frame.info().window_info.monitor_size.unwrap().y;
 
-        if _type.is_some() {
-            _frame.set_visible(false);
-        }
-        
         _frame.set_decorations(false);
         _frame.set_window_size(vec2(width + 1., height + 1.));
         _frame.set_window_pos(Pos2::ZERO);
@@ -61,6 +69,10 @@ impl ScreenshotView {
             let rect = egui::Rect::from_min_size(egui::Pos2::ZERO, egui::Vec2::new(width, height));
             ui.painter()
                 .rect_filled(rect, 0.0, Color32::from_rgba_unmultiplied(0, 0, 0, 30));
+            if self.window_picking {
+                self.window_picker(ui, ctx, _frame, rect, _type);
+                return;
+            }
             let response = ui.allocate_response(rect.size(), Sense::drag());
             let bound = response.rect.size();
             if response.drag_started() {
@@ -94,9 +106,7 @@ impl ScreenshotView {
                 {
                     self.dimension_selected.x = self.dimension_selected.x.abs();
                     self.dimension_selected.y = self.dimension_selected.y.abs();
-                    let tmp = self.starting_point;
-                    self.starting_point = self.ending_point;
-                    self.ending_point = tmp;
+                    std::mem::swap(&mut self.starting_point, &mut self.ending_point);
                 }
 
                 if self.dimension_selected.x > 50.0 && self.dimension_selected.y > 50.0 {
@@ -107,12 +117,7 @@ impl ScreenshotView {
                         self.dimension_selected.y = bound.y;
                     }
                     self.finished_selection = true;
-                    let disp = DisplayInfo::from_point(
-                        (self.starting_point.x as u32).try_into().unwrap(),
-                        (self.starting_point.y as u32).try_into().unwrap(),
-                    )
-                    .unwrap();
-                    self.screen_selected = disp.id;
+                    self.screen_selected = self.display_at(self.starting_point.x as i32, self.starting_point.y as i32);
                     *_type = Some(ScreenshotType::PartialScreen);
 
                 } else {
@@ -141,6 +146,20 @@ impl ScreenshotView {
                         if ui.button(RichText::new("🔲").size(30.0)).on_hover_text("Fullscreen").clicked() {
                             *_type = Some(ScreenshotType::FullScreen);
                         }
+                        if ui.button(RichText::new("🖥").size(30.0)).on_hover_text("Single monitor").clicked() {
+                            self.screen_selected = match self.monitor_choice {
+                                Some(id) => id,
+                                None => self.display_under_cursor(ctx, _frame),
+                            };
+                            *_type = Some(ScreenshotType::SingleScreen);
+                        }
+                        self.monitor_picker(ui);
+                        if ui.add(SelectableLabel::new(self.window_picking, RichText::new("🗔").size(30.0))).on_hover_text("Window").clicked() {
+                            self.window_picking = !self.window_picking;
+                            if self.window_picking {
+                                self.windows = window_utils::capturable_windows(window_utils::default_provider().as_ref());
+                            }
+                        }
 
                         
                         let mut _timer_delay = self.timer_delay;
@@ -152,8 +171,148 @@ impl ScreenshotView {
             
     }
 
+    /// Shrinks the window to a small countdown panel while a delayed capture is pending,
+    /// leaving the rest of the screen usable. Returns true when the user cancels.
+    pub fn countdown_ui(&self, ctx: &Context, _frame: &mut eframe::Frame, remaining: std::time::Duration) -> bool {
+        let monitor_width = _frame.info().window_info.monitor_size.map_or(640.0, |size| size.x);
+        _frame.set_decorations(false);
+        _frame.set_window_size(vec2(220.0, 170.0));
+        _frame.set_window_pos(pos2((monitor_width - 220.0) / 2.0, 30.0));
+        _frame.set_always_on_top(true);
+
+        let mut cancel = false;
+        let dark_blue_frame = Frame::default().fill(Color32::from_rgb(15, 22, 38)).inner_margin(10.0);
+        CentralPanel::default().frame(dark_blue_frame).show(ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.label(RichText::new("Capturing in").size(15.0));
+                ui.label(RichText::new(format!("{}", remaining.as_secs_f32().ceil() as u64)).size(60.0).strong());
+                if ui.button(RichText::new("✖").size(25.0)).on_hover_text("Cancel (Esc)").clicked() {
+                    cancel = true;
+                }
+            });
+        });
+        cancel
+    }
+
     pub fn get_timer_delay(&self) -> i32 {
         self.timer_delay
     }
 
+    /// Area to capture for the chosen screenshot type, in physical pixels.
+    pub fn capture_area(&self, screenshot_type: &ScreenshotType, pixels_per_point: f32) -> Option<CaptureArea> {
+        match screenshot_type {
+            ScreenshotType::FullScreen => Some(CaptureArea::AllScreens),
+            ScreenshotType::SingleScreen => Some(CaptureArea::Screen(self.screen_selected)),
+            ScreenshotType::PartialScreen => {
+                let min = self.starting_point.min(self.ending_point);
+                Some(CaptureArea::Region {
+                    x: (min.x * pixels_per_point) as i32,
+                    y: (min.y * pixels_per_point) as i32,
+                    width: (self.dimension_selected.x * pixels_per_point) as u32,
+                    height: (self.dimension_selected.y * pixels_per_point) as u32,
+                })
+            }
+            ScreenshotType::Window => self.selected_window.as_ref().map(|window| CaptureArea::Region {
+                x: window.x,
+                y: window.y,
+                width: window.width,
+                height: window.height,
+            }),
+        }
+    }
+
+    /// Highlights the window under the pointer and selects it on click.
+    fn window_picker(
+        &mut self,
+        ui: &mut Ui,
+        ctx: &Context,
+        _frame: &eframe::Frame,
+        rect: Rect,
+        _type: &mut Option<ScreenshotType>,
+    ) {
+        let response = ui.allocate_response(rect.size(), Sense::click());
+        let pixels_per_point = ctx.pixels_per_point();
+        let origin = _frame.info().window_info.position.unwrap_or(Pos2::ZERO);
+
+        if self.windows.is_empty() {
+            ui.painter().text(
+                rect.center(),
+                Align2::CENTER_CENTER,
+                "No windows available to capture",
+                FontId::proportional(20.0),
+                Color32::WHITE,
+            );
+            return;
+        }
+
+        let Some(pointer) = ctx.pointer_hover_pos() else { return };
+        let global = ((pointer + origin.to_vec2()).to_vec2() * pixels_per_point).round();
+        let Some(hovered) = window_utils::window_at(&self.windows, global.x as i32, global.y as i32) else { return };
+
+        let window_rect = hovered.screen_rect(pixels_per_point, origin);
+        ui.painter().rect(
+            window_rect,
+            Rounding::none(),
+            Color32::from_rgba_unmultiplied(18, 160, 215, 40),
+            Stroke::new(2.0, Color32::from_rgb(18, 160, 215)),
+        );
+        if !hovered.title.is_empty() {
+            ui.painter().text(
+                window_rect.left_top() + vec2(6.0, 6.0),
+                Align2::LEFT_TOP,
+                &hovered.title,
+                FontId::proportional(16.0),
+                Color32::WHITE,
+            );
+        }
+
+        if response.clicked() {
+            self.selected_window = Some(hovered.clone());
+            self.window_picking = false;
+            *_type = Some(ScreenshotType::Window);
+        }
+    }
+
+    fn monitor_picker(&mut self, ui: &mut Ui) {
+        let selected_text = match self.monitor_choice {
+            Some(id) => display_label(self.displays.iter().find(|d| d.id == id)),
+            None => "Under cursor".to_string(),
+        };
+        ComboBox::from_id_source("monitor_picker")
+            .selected_text(selected_text)
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.monitor_choice, None, "Under cursor");
+                for display in &self.displays {
+                    ui.selectable_value(&mut self.monitor_choice, Some(display.id), display_label(Some(display)));
+                }
+            })
+            .response
+            .on_hover_text("Monitor to capture");
+    }
+
+    /// Id of the display the pointer is currently on.
+    fn display_under_cursor(&self, ctx: &Context, _frame: &eframe::Frame) -> u32 {
+        let window_pos = _frame.info().window_info.position.unwrap_or(Pos2::ZERO);
+        let pointer = ctx.pointer_hover_pos().unwrap_or(Pos2::ZERO) + window_pos.to_vec2();
+        self.display_at(pointer.x as i32, pointer.y as i32)
+    }
+
+    /// Id of the display containing the given point, falling back to the primary one.
+    fn display_at(&self, x: i32, y: i32) -> u32 {
+        self.displays
+            .iter()
+            .find(|d| d.contains(x, y))
+            .or(self.displays.iter().find(|d| d.is_primary))
+            .or(self.displays.first())
+            .map_or(0, |d| d.id)
+    }
+
+}
+
+fn display_label(display: Option<&DisplayGeometry>) -> String {
+    match display {
+        Some(d) if d.is_primary => format!("Display {} ({}x{}, primary)", d.id, d.width, d.height),
+        Some(d) => format!("Display {} ({}x{})", d.id, d.width, d.height),
+        None => "Unknown display".to_string(),
+    }
 }
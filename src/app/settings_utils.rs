@@ -0,0 +1,199 @@
+use super::error_utils::YasaError;
+use super::painting_utils::DrawingShape;
+use super::pdf_utils::PageSize;
+use super::save_utils;
+use crate::app::ImgFormats;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Bumped whenever the layout of the config file changes in a non backwards compatible way.
+pub const SETTINGS_VERSION: u32 = 2;
+
+/// Everything remembered between runs, stored as TOML in the user's config directory.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub version: u32,
+    pub save_folder: PathBuf,
+    pub format: ImgFormats,
+    pub pdf_page: PageSize,
+    /// See `SavePath::bake_redactions`.
+    pub project_bake_redactions: bool,
+    pub filename_template: String,
+    pub delay: i32,
+    /// Action name -> shortcuts, e.g. `save = ["CTRL+S"]`.
+    pub shortcuts: BTreeMap<String, Vec<String>>,
+    pub tools: ToolSettings,
+}
+
+/// Last pen configuration used in the editor.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToolSettings {
+    pub stroke_width: f32,
+    /// Premultiplied RGBA, as stored by `egui::Color32`.
+    pub stroke_color: [u8; 4],
+    pub shape: DrawingShape,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            save_folder: std::env::current_dir().unwrap_or_default().join("target"),
+            format: ImgFormats::PNG,
+            pdf_page: PageSize::default(),
+            project_bake_redactions: true,
+            filename_template: save_utils::DEFAULT_NAME_TEMPLATE.to_string(),
+            delay: 0,
+            shortcuts: BTreeMap::new(),
+            tools: ToolSettings::default(),
+        }
+    }
+}
+
+impl Default for ToolSettings {
+    fn default() -> Self {
+        Self {
+            stroke_width: 3.0,
+            stroke_color: egui::Color32::from_rgba_unmultiplied(18, 160, 215, 255).to_array(),
+            shape: DrawingShape::Line,
+        }
+    }
+}
+
+impl ToolSettings {
+    pub fn stroke(&self) -> egui::Stroke {
+        let [r, g, b, a] = self.stroke_color;
+        egui::Stroke::new(self.stroke_width, egui::Color32::from_rgba_premultiplied(r, g, b, a))
+    }
+
+    pub fn from_tools(stroke: egui::Stroke, shape: DrawingShape) -> Self {
+        Self {
+            stroke_width: stroke.width,
+            stroke_color: stroke.color.to_array(),
+            shape,
+        }
+    }
+}
+
+pub fn config_path() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("yasa").join("config.toml"))
+}
+
+/// Loads the settings file, falling back to the defaults when it is missing.
+/// A corrupt or newer file is moved aside to `config.toml.bak`, and the error is returned
+/// alongside the defaults so that it can be reported to the user.
+pub fn load() -> (Settings, Option<YasaError>) {
+    match config_path() {
+        Some(path) => load_from(&path),
+        None => (Settings::default(), None),
+    }
+}
+
+fn load_from(path: &Path) -> (Settings, Option<YasaError>) {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return (Settings::default(), None),
+        Err(e) => return (Settings::default(), Some(e.into())),
+    };
+    match parse(&text) {
+        Ok(settings) => (settings, None),
+        Err(e) => {
+            let _ = fs::rename(path, path.with_extension("toml.bak"));
+            (Settings::default(), Some(e))
+        }
+    }
+}
+
+fn parse(text: &str) -> Result<Settings, YasaError> {
+    let mut value: toml::Table = toml::from_str(text).map_err(|e| YasaError::Settings(e.message().to_string()))?;
+    let version = value.get("version").and_then(toml::Value::as_integer).unwrap_or(0);
+    if version > SETTINGS_VERSION as i64 {
+        return Err(YasaError::Settings(format!(
+            "config version {} is newer than the supported one ({})",
+            version, SETTINGS_VERSION
+        )));
+    }
+    if version < 2 {
+        migrate_v1_shortcuts(&mut value);
+    }
+    let mut settings: Settings = value.try_into().map_err(|e: toml::de::Error| YasaError::Settings(e.message().to_string()))?;
+    settings.version = SETTINGS_VERSION;
+    Ok(settings)
+}
+
+/// Version 1 bound a single shortcut to each action (`save = "CTRL+S"`).
+fn migrate_v1_shortcuts(value: &mut toml::Table) {
+    if let Some(toml::Value::Table(shortcuts)) = value.get_mut("shortcuts") {
+        for (_, shortcut) in shortcuts.iter_mut() {
+            if let toml::Value::String(text) = shortcut {
+                *shortcut = toml::Value::Array(vec![toml::Value::String(text.clone())]);
+            }
+        }
+    }
+}
+
+pub fn store(settings: &Settings) -> Result<(), YasaError> {
+    let path = config_path().ok_or_else(|| YasaError::Settings("no config directory available".to_string()))?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let text = toml::to_string_pretty(settings).map_err(|e| YasaError::Settings(e.to_string()))?;
+    fs::write(path, text)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_a_newer_version() {
+        let text = format!("version = {}\n", SETTINGS_VERSION + 1);
+        assert!(matches!(parse(&text), Err(YasaError::Settings(_))));
+    }
+
+    #[test]
+    fn reads_the_version_1_shortcuts() {
+        let settings = parse("version = 1\n[shortcuts]\nsave = \"CTRL+S\"\nundo = [\"CTRL+Z\"]\n").unwrap();
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        assert_eq!(settings.shortcuts["save"], vec!["CTRL+S".to_string()]);
+        assert_eq!(settings.shortcuts["undo"], vec!["CTRL+Z".to_string()]);
+    }
+
+    #[test]
+    fn migrate_v1_shortcuts_wraps_single_shortcuts() {
+        let mut value: toml::Table = toml::from_str("[shortcuts]\nsave = \"CTRL+S\"\ncopy = [\"CTRL+C\", \"CTRL+INSERT\"]\n").unwrap();
+        migrate_v1_shortcuts(&mut value);
+        let shortcuts = value["shortcuts"].as_table().unwrap();
+        assert_eq!(shortcuts["save"], toml::Value::Array(vec![toml::Value::String("CTRL+S".to_string())]));
+        assert_eq!(shortcuts["copy"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn load_falls_back_to_the_defaults() {
+        let folder = std::env::temp_dir().join(format!("yasa-settings-{}", std::process::id()));
+        fs::create_dir_all(&folder).unwrap();
+        let path = folder.join("config.toml");
+
+        let (settings, error) = load_from(&path);
+        assert_eq!(settings, Settings::default());
+        assert!(error.is_none());
+
+        fs::write(&path, "delay = \"soon\"").unwrap();
+        let (settings, error) = load_from(&path);
+        assert_eq!(settings, Settings::default());
+        assert!(matches!(error, Some(YasaError::Settings(_))));
+        assert!(!path.exists());
+        assert_eq!(fs::read_to_string(folder.join("config.toml.bak")).unwrap(), "delay = \"soon\"");
+
+        fs::write(&path, "delay = 3").unwrap();
+        let (settings, error) = load_from(&path);
+        assert_eq!(settings.delay, 3);
+        assert!(error.is_none());
+        fs::remove_dir_all(folder).unwrap();
+    }
+}
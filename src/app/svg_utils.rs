@@ -0,0 +1,218 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use egui::{Color32, Pos2, Rect};
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+
+use super::error_utils::YasaError;
+use super::raster_utils::Primitive;
+use super::save_utils::{self, SavePath};
+
+pub const SVG_EXTENSION: &str = "svg";
+
+/// Writes `svg` to the folder and name in `save_path`, returning the full path written.
+pub fn save_svg(save_path: &SavePath, svg: &str) -> Result<PathBuf, YasaError> {
+    let pathname = save_utils::target_path(save_path, SVG_EXTENSION)?;
+    fs::write(&pathname, svg)?;
+    Ok(pathname)
+}
+
+/// SVG document showing `area` (in pixels), with the `background` PNG covering it and `elements` on top.
+/// Coordinates are pixels of the whole screenshot, so the annotations need no translation once cropped.
+pub fn document(area: Rect, background: &[u8], elements: &[String]) -> String {
+    let mut svg = String::new();
+    let _ = writeln!(svg, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="{}" height="{}" viewBox="{} {} {} {}">"#,
+        area.width(),
+        area.height(),
+        area.min.x,
+        area.min.y,
+        area.width(),
+        area.height()
+    );
+    let _ = writeln!(svg, "  {}", image(area, background));
+    for element in elements {
+        let _ = writeln!(svg, "  {}", element);
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// PNG image stretched over `rect`.
+pub fn image(rect: Rect, png: &[u8]) -> String {
+    format!(
+        r#"<image x="{}" y="{}" width="{}" height="{}" preserveAspectRatio="none" xlink:href="data:image/png;base64,{}"/>"#,
+        rect.min.x,
+        rect.min.y,
+        rect.width(),
+        rect.height(),
+        STANDARD.encode(png)
+    )
+}
+
+/// Open line through `points`, with round ends like the ones drawn on the canvas.
+pub fn polyline(points: &[Pos2], width: f32, color: Color32) -> String {
+    format!(
+        r#"<polyline points="{}" fill="none" {} stroke-width="{}" stroke-linecap="round" stroke-linejoin="round"/>"#,
+        coordinates(points),
+        paint("stroke", color),
+        width
+    )
+}
+
+pub fn polygon(points: &[Pos2], color: Color32) -> String {
+    format!(r#"<polygon points="{}" {}/>"#, coordinates(points), paint("fill", color))
+}
+
+pub fn rect(rect: Rect, style: &str) -> String {
+    format!(
+        r#"<rect x="{}" y="{}" width="{}" height="{}" {}/>"#,
+        rect.min.x,
+        rect.min.y,
+        rect.width(),
+        rect.height(),
+        style
+    )
+}
+
+/// Ellipse inscribed in `rect`.
+pub fn ellipse(rect: Rect, style: &str) -> String {
+    let center = rect.center();
+    format!(
+        r#"<ellipse cx="{}" cy="{}" rx="{}" ry="{}" {}/>"#,
+        center.x,
+        center.y,
+        rect.width() / 2.0,
+        rect.height() / 2.0,
+        style
+    )
+}
+
+/// Style of a filled shape, or of an outlined one `width` pixels wide.
+pub fn shape_style(color: Color32, filled: bool, width: f32) -> String {
+    if filled {
+        paint("fill", color)
+    } else {
+        format!(r#"fill="none" {} stroke-width="{}" stroke-linejoin="round""#, paint("stroke", color), width)
+    }
+}
+
+/// Lines as polylines and filled parts as polygons, grouped so that a translucent colour is blended once where they overlap.
+pub fn primitives(primitives: &[Primitive], color: Color32) -> String {
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    let opaque = Color32::from_rgb(r, g, b);
+    let parts: String = primitives
+        .iter()
+        .map(|primitive| match primitive {
+            Primitive::Stroke(points, width) => polyline(points, *width, opaque),
+            Primitive::Fill(points) => polygon(points, opaque),
+        })
+        .collect();
+    if a < 255 {
+        format!(r#"<g opacity="{:.3}">{}</g>"#, a as f32 / 255.0, parts)
+    } else {
+        group(&[parts])
+    }
+}
+
+/// Several lines of text, `baseline` being the baseline of the first one.
+pub fn text(text: &str, baseline: Pos2, size: f32, line_height: f32, family: &str, color: Color32) -> String {
+    let mut lines = String::new();
+    for (i, line) in text.lines().enumerate() {
+        let y = baseline.y + i as f32 * line_height;
+        let _ = write!(lines, r#"<tspan x="{}" y="{}">{}</tspan>"#, baseline.x, y, escape(line));
+    }
+    format!(
+        r#"<text font-family="{}" font-size="{}" {} xml:space="preserve">{}</text>"#,
+        escape(family),
+        size,
+        paint("fill", color),
+        lines
+    )
+}
+
+pub fn group(elements: &[String]) -> String {
+    format!("<g>{}</g>", elements.concat())
+}
+
+/// `fill` or `stroke` attribute of `color`, with its opacity when it is translucent.
+fn paint(attribute: &str, color: Color32) -> String {
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    let mut paint = format!(r##"{}="#{:02x}{:02x}{:02x}""##, attribute, r, g, b);
+    if a < 255 {
+        let _ = write!(paint, r#" {}-opacity="{:.3}""#, attribute, a as f32 / 255.0);
+    }
+    paint
+}
+
+fn coordinates(points: &[Pos2]) -> String {
+    points.iter().map(|p| format!("{},{}", p.x, p.y)).collect::<Vec<_>>().join(" ")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egui::pos2;
+
+    /// Bytes of the base64 `data:` URI of the first image of `svg`.
+    fn embedded_png(svg: &str) -> Vec<u8> {
+        let start = svg.find("data:image/png;base64,").unwrap() + "data:image/png;base64,".len();
+        let end = start + svg[start..].find('"').unwrap();
+        STANDARD.decode(&svg[start..end]).unwrap()
+    }
+
+    #[test]
+    fn document_shows_the_area_in_pixels_of_the_screenshot() {
+        let area = Rect::from_min_size(pos2(10.0, 20.0), egui::vec2(300.0, 200.0));
+        let svg = document(area, b"png", &["<g/>".to_string()]);
+        assert!(svg.starts_with(r#"<?xml version="1.0" encoding="UTF-8"?>"#));
+        assert!(svg.contains(r#"width="300" height="200" viewBox="10 20 300 200""#), "{}", svg);
+        assert!(svg.contains(r#"<image x="10" y="20" width="300" height="200""#), "{}", svg);
+        assert_eq!(embedded_png(&svg), b"png");
+        assert!(svg.trim_end().ends_with("<g/>\n</svg>"));
+    }
+
+    #[test]
+    fn translucent_colours_get_an_opacity() {
+        let opaque = polyline(&[pos2(0.0, 0.0), pos2(4.5, 2.0)], 3.0, Color32::from_rgb(255, 0, 16));
+        assert_eq!(
+            opaque,
+            r##"<polyline points="0,0 4.5,2" fill="none" stroke="#ff0010" stroke-width="3" stroke-linecap="round" stroke-linejoin="round"/>"##
+        );
+        // Colours are stored premultiplied: channels other than 0 and 255 may come back off by one.
+        let translucent = polyline(&[pos2(0.0, 0.0)], 3.0, Color32::from_rgba_unmultiplied(255, 0, 255, 128));
+        assert!(translucent.contains(r##"stroke="#ff00ff" stroke-opacity="0.502""##), "{}", translucent);
+        assert_eq!(shape_style(Color32::from_rgba_unmultiplied(0, 0, 255, 64), true, 2.0), r##"fill="#0000ff" fill-opacity="0.251""##);
+    }
+
+    #[test]
+    fn translucent_primitives_are_grouped_under_one_opacity() {
+        let color = Color32::from_rgba_unmultiplied(0, 255, 0, 51);
+        let parts = [Primitive::Stroke(vec![pos2(0.0, 0.0), pos2(5.0, 0.0)], 2.0), Primitive::Fill(vec![pos2(5.0, -2.0), pos2(8.0, 0.0), pos2(5.0, 2.0)])];
+        let svg = primitives(&parts, color);
+        assert!(svg.starts_with(r#"<g opacity="0.200">"#), "{}", svg);
+        assert!(!svg.contains("-opacity"), "{}", svg);
+        assert!(svg.contains(r##"stroke="#00ff00""##) && svg.contains(r##"fill="#00ff00""##), "{}", svg);
+    }
+
+    #[test]
+    fn text_is_escaped() {
+        let svg = text("a < b & \"c\"\n> d", pos2(1.0, 10.0), 12.0, 14.0, "Ubuntu, \"Sans\"", Color32::BLACK);
+        assert!(svg.contains(r#"<tspan x="1" y="10">a &lt; b &amp; &quot;c&quot;</tspan><tspan x="1" y="24">&gt; d</tspan>"#), "{}", svg);
+        assert!(svg.contains(r#"font-family="Ubuntu, &quot;Sans&quot;""#), "{}", svg);
+    }
+
+    #[test]
+    fn image_is_stretched_over_its_rect() {
+        let svg = image(Rect::from_min_max(pos2(2.0, 3.0), pos2(12.0, 8.0)), &[0, 1, 2, 255]);
+        assert!(svg.starts_with(r#"<image x="2" y="3" width="10" height="5" preserveAspectRatio="none""#), "{}", svg);
+        assert_eq!(embedded_png(&svg), [0, 1, 2, 255]);
+    }
+}
@@ -0,0 +1,78 @@
+use egui::{FontData, FontDefinitions, FontFamily, Vec2};
+use rusttype::{point, Font, Scale};
+use std::sync::OnceLock;
+
+use super::raster_utils::StrokeMask;
+
+/// Font used for text annotations. The same file is given to egui and to the exporter,
+/// so that the text saved in the image looks like the text shown on the canvas.
+const ANNOTATION_FONT: &[u8] = include_bytes!("../../assets/fonts/Ubuntu-Light.ttf");
+const ANNOTATION_FONT_NAME: &str = "yasa-annotation";
+/// The bundled font, as named in exported SVG files, with fallbacks for viewers that do not have it.
+pub const ANNOTATION_FONT_SVG_FAMILY: &str = "Ubuntu Light, Ubuntu, sans-serif";
+
+pub fn font_family() -> FontFamily {
+    FontFamily::Name(ANNOTATION_FONT_NAME.into())
+}
+
+/// Registers the annotation font with egui, next to the default ones.
+pub fn install_font(ctx: &egui::Context) {
+    let mut fonts = FontDefinitions::default();
+    fonts
+        .font_data
+        .insert(ANNOTATION_FONT_NAME.to_owned(), FontData::from_static(ANNOTATION_FONT));
+    fonts
+        .families
+        .insert(font_family(), vec![ANNOTATION_FONT_NAME.to_owned()]);
+    ctx.set_fonts(fonts);
+}
+
+fn font() -> &'static Font<'static> {
+    static FONT: OnceLock<Font<'static>> = OnceLock::new();
+    FONT.get_or_init(|| Font::try_from_bytes(ANNOTATION_FONT).expect("bundled font is valid"))
+}
+
+/// Distance between two baselines, computed like egui does for its rows.
+pub fn line_height(size: f32) -> f32 {
+    let v_metrics = font().v_metrics(Scale::uniform(size));
+    v_metrics.ascent - v_metrics.descent + v_metrics.line_gap
+}
+
+/// Distance from the top of a line to its baseline.
+pub fn ascent(size: f32) -> f32 {
+    font().v_metrics(Scale::uniform(size)).ascent
+}
+
+/// Width and height of `text` written with a font of `size` pixels.
+pub fn text_extent(text: &str, size: f32) -> Vec2 {
+    let scale = Scale::uniform(size);
+    let width = text
+        .lines()
+        .map(|line| {
+            font()
+                .layout(line, scale, point(0.0, 0.0))
+                .last()
+                .map_or(0.0, |glyph| glyph.position().x + glyph.unpositioned().h_metrics().advance_width)
+        })
+        .fold(0.0, f32::max);
+    let lines = text.lines().count().max(1);
+    Vec2::new(width, lines as f32 * line_height(size))
+}
+
+/// Adds the glyphs of `text`, with its top-left corner at (`x`, `y`), to `mask`: compositing it then blends
+/// the text like the other annotations, translucent colours included.
+pub fn draw_text(mask: &mut StrokeMask, text: &str, x: f32, y: f32, size: f32) {
+    let scale = Scale::uniform(size);
+    let x = x.round() as i32;
+    for (i, line) in text.lines().enumerate() {
+        let line_y = (y + i as f32 * line_height(size)).round() as i32;
+        for glyph in font().layout(line, scale, point(0.0, ascent(size))) {
+            let Some(bounds) = glyph.pixel_bounding_box() else {
+                continue;
+            };
+            glyph.draw(|glyph_x, glyph_y, coverage| {
+                mask.cover_pixel(x + bounds.min.x + glyph_x as i32, line_y + bounds.min.y + glyph_y as i32, coverage);
+            });
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use egui::{Pos2, Rect};
+
+/// A top-level application window, with its bounds in physical pixels
+/// relative to the top left corner of the first display.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WindowInfo {
+    pub id: u32,
+    pub title: String,
+    pub pid: Option<u32>,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl WindowInfo {
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && y >= self.y && x < self.x + self.width as i32 && y < self.y + self.height as i32
+    }
+
+    /// Window bounds converted to egui points on the overlay drawn at `origin`.
+    pub fn screen_rect(&self, pixels_per_point: f32, origin: Pos2) -> Rect {
+        Rect::from_min_size(
+            Pos2::new(self.x as f32 / pixels_per_point, self.y as f32 / pixels_per_point) - origin.to_vec2(),
+            egui::vec2(self.width as f32, self.height as f32) / pixels_per_point,
+        )
+    }
+}
+
+/// Source of the top-level windows the user can pick from.
+/// Windows are listed in stacking order, bottom-most first.
+pub trait WindowProvider {
+    fn windows(&self) -> Vec<WindowInfo>;
+}
+
+/// Used where no window system integration is available: window mode then has nothing to pick.
+pub struct NoWindowProvider;
+
+impl WindowProvider for NoWindowProvider {
+    fn windows(&self) -> Vec<WindowInfo> {
+        vec![]
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub struct X11WindowProvider;
+
+#[cfg(target_os = "linux")]
+impl WindowProvider for X11WindowProvider {
+    fn windows(&self) -> Vec<WindowInfo> {
+        x11_windows().unwrap_or_default()
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn x11_windows() -> Result<Vec<WindowInfo>, Box<dyn std::error::Error>> {
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xproto::{AtomEnum, ConnectionExt, MapState};
+
+    let (conn, screen_num) = x11rb::connect(None)?;
+    let root = conn.setup().roots[screen_num].root;
+    let intern = |name: &[u8]| -> Result<u32, Box<dyn std::error::Error>> {
+        Ok(conn.intern_atom(false, name)?.reply()?.atom)
+    };
+    let client_list = intern(b"_NET_CLIENT_LIST_STACKING")?;
+    let net_wm_name = intern(b"_NET_WM_NAME")?;
+    let net_wm_pid = intern(b"_NET_WM_PID")?;
+    let utf8_string = intern(b"UTF8_STRING")?;
+
+    let mut ids: Vec<u32> = conn
+        .get_property(false, root, client_list, AtomEnum::WINDOW, 0, u32::MAX)?
+        .reply()?
+        .value32()
+        .map(|ids| ids.collect())
+        .unwrap_or_default();
+    if ids.is_empty() {
+        // Window managers without EWMH support: fall back to the children of the root window
+        ids = conn.query_tree(root)?.reply()?.children;
+    }
+
+    let mut windows = vec![];
+    for id in ids {
+        let Ok(attributes) = conn.get_window_attributes(id)?.reply() else { continue };
+        if attributes.map_state != MapState::VIEWABLE {
+            continue;
+        }
+        // The window may close while the list is read: leave it out rather than the whole list.
+        let Ok(geometry) = conn.get_geometry(id)?.reply() else { continue };
+        let Ok(position) = conn.translate_coordinates(id, root, 0, 0)?.reply() else { continue };
+        let title = conn
+            .get_property(false, id, net_wm_name, utf8_string, 0, 1024)?
+            .reply()
+            .map(|reply| String::from_utf8_lossy(&reply.value).to_string())
+            .unwrap_or_default();
+        let pid = conn
+            .get_property(false, id, net_wm_pid, AtomEnum::CARDINAL, 0, 1)?
+            .reply()
+            .ok()
+            .and_then(|reply| reply.value32().and_then(|mut values| values.next()));
+
+        windows.push(WindowInfo {
+            id,
+            title,
+            pid,
+            x: position.dst_x as i32,
+            y: position.dst_y as i32,
+            width: geometry.width as u32,
+            height: geometry.height as u32,
+        });
+    }
+    Ok(windows)
+}
+
+pub fn default_provider() -> Box<dyn WindowProvider> {
+    #[cfg(target_os = "linux")]
+    if std::env::var_os("DISPLAY").is_some() {
+        return Box::new(X11WindowProvider);
+    }
+    Box::new(NoWindowProvider)
+}
+
+/// Lists the windows of other applications, leaving out YASA's own overlay.
+pub fn capturable_windows(provider: &dyn WindowProvider) -> Vec<WindowInfo> {
+    let own_pid = std::process::id();
+    provider
+        .windows()
+        .into_iter()
+        .filter(|w| w.pid != Some(own_pid) && w.width > 0 && w.height > 0)
+        .collect()
+}
+
+/// Topmost window containing the given point (physical pixels).
+pub fn window_at(windows: &[WindowInfo], x: i32, y: i32) -> Option<&WindowInfo> {
+    windows.iter().rev().find(|w| w.contains(x, y))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Window list set by the test, in stacking order.
+    struct FakeWindowProvider(Vec<WindowInfo>);
+
+    impl WindowProvider for FakeWindowProvider {
+        fn windows(&self) -> Vec<WindowInfo> {
+            self.0.clone()
+        }
+    }
+
+    fn window(id: u32, pid: Option<u32>, x: i32, y: i32, width: u32, height: u32) -> WindowInfo {
+        WindowInfo { id, title: format!("window {}", id), pid, x, y, width, height }
+    }
+
+    #[test]
+    fn capturable_windows_leave_out_own_and_empty_windows() {
+        let own_pid = std::process::id();
+        let provider = FakeWindowProvider(vec![
+            window(1, Some(own_pid + 1), 0, 0, 100, 100),
+            window(2, Some(own_pid), 0, 0, 1920, 1080),
+            window(3, None, 10, 10, 0, 50),
+            window(4, None, 50, 50, 200, 100),
+        ]);
+        let ids: Vec<u32> = capturable_windows(&provider).iter().map(|w| w.id).collect();
+        assert_eq!(ids, vec![1, 4]);
+    }
+
+    #[test]
+    fn capturable_windows_of_no_provider_is_empty() {
+        assert!(capturable_windows(&NoWindowProvider).is_empty());
+    }
+
+    #[test]
+    fn window_at_picks_the_topmost_window() {
+        let windows = vec![window(1, None, 0, 0, 100, 100), window(2, None, 50, 50, 100, 100)];
+        assert_eq!(window_at(&windows, 10, 10).map(|w| w.id), Some(1));
+        assert_eq!(window_at(&windows, 60, 60).map(|w| w.id), Some(2));
+        assert_eq!(window_at(&windows, 149, 149).map(|w| w.id), Some(2));
+        assert_eq!(window_at(&windows, 150, 150), None);
+        assert_eq!(window_at(&windows, -1, 10), None);
+    }
+
+    #[test]
+    fn window_at_handles_windows_left_of_the_first_display() {
+        let windows = vec![window(1, None, -200, 0, 300, 100)];
+        assert_eq!(window_at(&windows, -150, 50).map(|w| w.id), Some(1));
+        assert_eq!(window_at(&windows, 100, 50), None);
+    }
+}
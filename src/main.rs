@@ -8,6 +8,15 @@ use crate::app::Views;
 
 #[cfg(not(target_arch = "wasm32"))]
 fn main() -> eframe::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("capture") {
+        std::process::exit(app::cli_utils::run_capture(&args[1..]));
+    }
+    // `yasa shot.png` or `yasa notes.yasa` opens the file in the editor.
+    let file = match app::cli_utils::editor_file(&args) {
+        Ok(file) => file,
+        Err(code) => std::process::exit(code),
+    };
 
     let native_options = eframe::NativeOptions {
         min_window_size: Some([300.0, 200.0].into()),
@@ -18,12 +27,19 @@ fn main() -> eframe::Result<()> {
     eframe::run_native(
         "YASA",
         native_options,
-        Box::new(|cc| Box::new(app::YasaApp::new(cc))),
+        Box::new(move |cc| {
+            let mut app = app::YasaApp::new(cc);
+            if let Some(file) = file {
+                app.open_path(&cc.egui_ctx, &file);
+            }
+            Box::new(app)
+        }),
     )
 }
 
 impl eframe::App for YasaApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.poll_scheduled_capture(_frame);
         match self.view {
             Views::Home => {
                 _frame.set_visible(true);
@@ -36,6 +52,10 @@ impl eframe::App for YasaApp {
         }
     }
     
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.store_settings();
+    }
+
     fn clear_color(&self, _visuals: &egui::Visuals) -> [f32; 4] {
         egui::Color32::TRANSPARENT.to_normalized_gamma_f32()
     }